@@ -0,0 +1,159 @@
+//! Renders scenes headlessly and compares them against reference images in `golden/reference`.
+//!
+//! A missing reference image is a failure, so a test can't pass without one being committed.
+//! Setting the `GOLDEN_BLESS` environment variable saves the rendered frames as the references
+//! instead, which is how new tests get their references and intentional visual changes are
+//! accepted.
+//! On a mismatch, the rendered frame and a diff image are written to `golden/output`.
+//!
+//! Rendering needs an adapter, and the references have to be made on one, so the golden tests
+//! are ignored by a plain `cargo test`. Run them with `cargo test -p golden -- --ignored`,
+//! once with `GOLDEN_BLESS=1` on a machine with a GPU to write the references, and commit those.
+use hackstead_scene::{controls::Controls, HacksteadScene};
+use hexa::Scene;
+use image::{Rgba, RgbaImage};
+use std::path::PathBuf;
+
+/// How different a frame may be from its reference and still pass.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// How far apart any channel of two pixels can be before they're considered different.
+    pub channel: u8,
+    /// The fraction of pixels that may differ before the comparison fails.
+    pub mismatched: f32,
+}
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel: 8,
+            mismatched: 0.001,
+        }
+    }
+}
+
+pub struct Comparison {
+    pub mismatched: usize,
+    pub total: usize,
+    /// Mismatched pixels in red, over a faded copy of the reference.
+    pub diff: RgbaImage,
+}
+impl Comparison {
+    pub fn passes(&self, tolerance: Tolerance) -> bool {
+        self.mismatched as f32 <= self.total as f32 * tolerance.mismatched
+    }
+}
+
+/// A headless renderer, or `None` if this machine can't provide one (say, a build box with
+/// neither a GPU nor a software adapter) and `GOLDEN_SKIP_NO_ADAPTER` is set, in which case
+/// golden tests should be skipped. Without that set, not finding an adapter is an error,
+/// so a broken setup can't pass for a passing test.
+pub fn renderer(width: u32, height: u32) -> Result<Option<render::Renderer>, failure::Error> {
+    // the same sample count everywhere, even on software adapters that can't do more
+    let settings = render::RenderSettings {
+        msaa: 4,
+        ..Default::default()
+    };
+    match render::Renderer::headless(width, height, settings) {
+        Ok(r) => Ok(Some(r)),
+        Err(e) if std::env::var_os("GOLDEN_SKIP_NO_ADAPTER").is_some() => {
+            log::warn!("can't render headlessly: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Sets up a HacksteadScene with the given controls, lets it upload its tiles, sprites and
+/// camera to the renderer, and captures the result.
+pub fn render_hackstead(
+    renderer: &mut render::Renderer,
+    controls: Controls,
+) -> Result<RgbaImage, failure::Error> {
+    let mut scene = HacksteadScene::with_controls(renderer, controls);
+    scene.update(renderer);
+    renderer.capture()
+}
+
+pub fn compare(actual: &RgbaImage, expected: &RgbaImage, channel_tolerance: u8) -> Comparison {
+    let mut mismatched = 0;
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let e = expected.get_pixel(x, y);
+        let matches = actual
+            .get_pixel(x, y)
+            .0
+            .iter()
+            .zip(e.0.iter())
+            .all(|(&a, &e)| (a as i16 - e as i16).abs() <= channel_tolerance as i16);
+
+        if matches {
+            let [r, g, b, _] = e.0;
+            let faded = ((r as u16 + g as u16 + b as u16) / 9) as u8;
+            Rgba([faded, faded, faded, 255])
+        } else {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        }
+    });
+
+    Comparison {
+        mismatched,
+        total: (expected.width() * expected.height()) as usize,
+        diff,
+    }
+}
+
+fn golden_dir(sub: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(sub)
+}
+
+/// Compares `actual` against the reference image called `name`, writing the reference instead
+/// if `GOLDEN_BLESS` is set, and writing the frame and a diff on failure.
+pub fn check(name: &str, actual: &RgbaImage, tolerance: Tolerance) -> Result<(), failure::Error> {
+    let reference_path = golden_dir("reference").join(format!("{}.png", name));
+
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        log::warn!("writing new reference image {}", reference_path.display());
+        std::fs::create_dir_all(golden_dir("reference"))?;
+        actual.save(&reference_path)?;
+        return Ok(());
+    }
+
+    if !reference_path.exists() {
+        failure::bail!(
+            "{} has no reference image, run with GOLDEN_BLESS=1 to write {}",
+            name,
+            reference_path.display()
+        );
+    }
+
+    let expected = image::open(&reference_path)?.to_rgba();
+    if expected.dimensions() != actual.dimensions() {
+        failure::bail!(
+            "{} is {:?}, but the reference image is {:?}",
+            name,
+            actual.dimensions(),
+            expected.dimensions()
+        );
+    }
+
+    let comparison = compare(actual, &expected, tolerance.channel);
+    if comparison.passes(tolerance) {
+        return Ok(());
+    }
+
+    let output = golden_dir("output");
+    std::fs::create_dir_all(&output)?;
+    let actual_path = output.join(format!("{}.actual.png", name));
+    let diff_path = output.join(format!("{}.diff.png", name));
+    actual.save(&actual_path)?;
+    comparison.diff.save(&diff_path)?;
+
+    failure::bail!(
+        "{} differs from its reference in {} of {} pixels, see {} and {}",
+        name,
+        comparison.mismatched,
+        comparison.total,
+        actual_path.display(),
+        diff_path.display()
+    )
+}
@@ -0,0 +1,55 @@
+use hackstead_scene::controls::{camera::CameraControls, tiling, Controls};
+use hackstead_scene::terrain::Generator;
+
+fn assert_golden(name: &str, camera: CameraControls, tiling: tiling::Data) {
+    let mut renderer = match golden::renderer(640, 360).expect("couldn't make a renderer") {
+        Some(r) => r,
+        None => return eprintln!("no adapter available, skipping golden test {}", name),
+    };
+
+    let frame = golden::render_hackstead(&mut renderer, Controls::with_settings(camera, tiling))
+        .expect("couldn't render scene");
+
+    golden::check(name, &frame, Default::default()).unwrap_or_else(|e| panic!("{}", e));
+}
+
+#[test]
+#[ignore = "needs a GPU and the reference images, see the golden crate's docs"]
+fn overview() {
+    assert_golden(
+        "overview",
+        CameraControls::new(0.5, 27.5, 0.5, 50.0),
+        tiling::Data {
+            elevation: 2.3,
+            size: 15,
+            seed: 42,
+            infinite: false,
+            sea_level: 0.0,
+            generator: Generator::Perlin,
+            octaves: 4,
+        },
+    );
+}
+
+#[test]
+#[ignore = "needs a GPU and the reference images, see the golden crate's docs"]
+fn close_up() {
+    assert_golden(
+        "close_up",
+        CameraControls::new(
+            std::f32::consts::PI / 2.0,
+            3.0,
+            std::f32::consts::PI / 2.0,
+            6.0,
+        ),
+        tiling::Data {
+            elevation: 0.1,
+            size: 5,
+            seed: 42,
+            infinite: false,
+            sea_level: 0.0,
+            generator: Generator::Perlin,
+            octaves: 4,
+        },
+    );
+}
@@ -7,6 +7,17 @@ pub enum Message {
     HeightChanged(f32),
     AngleChanged(f32),
     DistanceChanged(f32),
+    /// Puts every slider back where it was, for undoing and redoing.
+    Restored(Settings),
+}
+
+/// Where each of the camera sliders is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub fov: f32,
+    pub height: f32,
+    pub angle: f32,
+    pub distance: f32,
 }
 
 #[derive(Default, Debug)]
@@ -39,6 +50,27 @@ impl Default for CameraControls {
     }
 }
 
+impl CameraControls {
+    pub fn new(fov: f32, height: f32, angle: f32, distance: f32) -> Self {
+        Self {
+            fov,
+            height,
+            angle,
+            distance,
+            sliders: Default::default(),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        Settings {
+            fov: self.fov,
+            height: self.height,
+            angle: self.angle,
+            distance: self.distance,
+        }
+    }
+}
+
 impl Program for CameraControls {
     type Renderer = Renderer;
     type Message = Message;
@@ -51,6 +83,14 @@ impl Program for CameraControls {
             HeightChanged(height) => self.height = height,
             AngleChanged(angle) => self.angle = angle,
             DistanceChanged(distance) => self.distance = distance,
+            Restored(settings) => {
+                *self = Self::new(
+                    settings.fov,
+                    settings.height,
+                    settings.angle,
+                    settings.distance,
+                )
+            }
         }
 
         Command::none()
@@ -0,0 +1,89 @@
+use crate::editor::Tool;
+use hexa::iced_wgpu::Renderer;
+use hexa::iced_winit::{
+    slider, Align, Checkbox, Column, Command, Element, Program, Radio, Slider, Text,
+};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    EditingToggled(bool),
+    ToolPicked(Tool),
+    BrushChanged(u32),
+    TexturePicked(usize),
+    /// The names of the tile textures the renderer has, in order of their ids.
+    PaletteLoaded(Vec<String>),
+}
+
+/// The Edit tab. While editing, clicking a tile uses the tool on it,
+/// and on every tile within `brush` rings of it.
+#[derive(Default, Debug)]
+pub struct EditControls {
+    pub editing: bool,
+    pub tool: Tool,
+    pub brush: u32,
+    pub palette: Vec<String>,
+    /// The index of the texture paint tools paint with, in `palette`.
+    pub texture: usize,
+    brush_slider: slider::State,
+}
+
+impl Program for EditControls {
+    type Renderer = Renderer;
+    type Message = Message;
+
+    fn update(&mut self, message: Message) -> Command<Message> {
+        use Message::*;
+
+        match message {
+            EditingToggled(editing) => self.editing = editing,
+            ToolPicked(tool) => self.tool = tool,
+            BrushChanged(brush) => self.brush = brush,
+            TexturePicked(texture) => self.texture = texture,
+            PaletteLoaded(palette) => self.palette = palette,
+        }
+
+        Command::none()
+    }
+
+    fn view(&mut self) -> Element<Message, Renderer> {
+        let tool = self.tool;
+        let tools = Tool::ALL.iter().fold(Column::new().spacing(5), |c, &t| {
+            c.push(Radio::new(t, t.label(), Some(tool), Message::ToolPicked))
+        });
+
+        let mut c = Column::new()
+            .spacing(25)
+            .padding(10)
+            .push(Checkbox::new(
+                self.editing,
+                "Editing",
+                Message::EditingToggled,
+            ))
+            .push(tools)
+            .push(
+                Column::new()
+                    .spacing(2)
+                    .align_items(Align::Center)
+                    .push(Text::new(format!("Brush Size ({} rings)", self.brush)).size(20))
+                    .push(Slider::new(
+                        &mut self.brush_slider,
+                        0.0..=4.0,
+                        self.brush as f32,
+                        move |b| Message::BrushChanged(b.round() as u32),
+                    )),
+            );
+
+        if let Tool::PaintHat | Tool::PaintButt = tool {
+            let texture = self.texture;
+            c =
+                c.push(self.palette.iter().enumerate().fold(
+                    Column::new().spacing(5),
+                    |c, (i, name)| {
+                        c.push(Radio::new(i, name, Some(texture), Message::TexturePicked))
+                    },
+                ));
+        }
+
+        c.into()
+    }
+}
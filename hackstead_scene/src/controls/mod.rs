@@ -5,7 +5,7 @@ use hexa::iced_winit::{
 };
 use std::collections::HashMap;
 
-mod camera;
+pub mod camera;
 use camera::CameraControls;
 
 pub mod tiling;
@@ -13,11 +13,25 @@ pub use style::DarkIce;
 
 use tiling::TilingControls;
 
+pub mod time;
+use time::TimeControls;
+
+pub mod save;
+use save::{SaveControls, SaveDir};
+
+pub mod edit;
+use edit::EditControls;
+
 #[derive(Debug, Clone)]
 pub enum Message {
     SetTab(Tab),
     Camera(camera::Message),
     Tiling(tiling::Message),
+    Time(time::Message),
+    Save(save::Message),
+    Edit(edit::Message),
+    /// Replaces the camera and tiling controls with those a map was saved with.
+    MapLoaded(crate::map::MapCamera, tiling::Data),
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -25,10 +39,19 @@ pub enum Tab {
     Home,
     Camera,
     Tiling,
+    Time,
+    Edit,
     Save,
 }
 impl Tab {
-    const ALL: &'static [Tab] = &[Tab::Home, Tab::Camera, Tab::Tiling, Tab::Save];
+    const ALL: &'static [Tab] = &[
+        Tab::Home,
+        Tab::Camera,
+        Tab::Tiling,
+        Tab::Time,
+        Tab::Edit,
+        Tab::Save,
+    ];
 }
 impl Default for Tab {
     fn default() -> Self {
@@ -46,10 +69,65 @@ pub struct Controls {
     pub home_button: button::State,
     pub camera_tab: CameraControls,
     pub tiling_tab: TilingControls,
+    /// Saves from before there was a time of day start in the afternoon.
+    #[serde(default)]
+    pub time_tab: TimeControls,
+    #[serde(skip)]
+    pub edit_tab: EditControls,
+    #[serde(skip)]
+    pub save_tab: SaveControls,
+    /// Where the Save tab saves to and loads from.
+    #[serde(skip)]
+    pub saves: SaveDir,
 }
 
 impl Controls {
     pub fn new() -> Controls {
+        Self::open(SaveDir::default())
+    }
+
+    /// The controls saved in the default slot of this save directory, or the defaults if
+    /// nothing's been saved yet. If the save can't be loaded, the defaults are used and
+    /// the controls start on the Save tab, showing why.
+    /// If the default slot has a map, the scene is asked to load it too.
+    pub fn open(saves: SaveDir) -> Controls {
+        let (save, error) = match save::load_default(&saves) {
+            Ok(save) => (save.unwrap_or_default(), None),
+            Err(e) => (Default::default(), Some(e)),
+        };
+
+        let mut controls = Self::from_save(Self { saves, ..save });
+        controls.save_tab.refresh(&controls.saves);
+        controls.save_tab.map = controls
+            .saves
+            .map_path(save::DEFAULT_SLOT)
+            .ok()
+            .filter(|p| p.exists())
+            .map(save::MapRequest::Load);
+        if let Some(e) = error {
+            controls
+                .save_tab
+                .report(Err(format!("couldn't load the last save: {}", e)));
+            controls.tab = Tab::Save;
+        }
+        controls
+    }
+
+    /// Controls with the given camera and tiling settings, ignoring anything saved.
+    pub fn with_settings(camera_tab: CameraControls, tiling_data: tiling::Data) -> Controls {
+        Self::from_save(Self {
+            camera_tab,
+            tiling_tab: TilingControls::new(tiling_data),
+            ..Default::default()
+        })
+    }
+
+    /// Whether something's being typed in, so keys shouldn't do anything else.
+    pub fn typing(&self) -> bool {
+        self.tab == Tab::Save && self.save_tab.typing()
+    }
+
+    fn from_save(save: Controls) -> Controls {
         Self {
             tab: Tab::Home,
             tab_buttons: Tab::ALL
@@ -57,13 +135,7 @@ impl Controls {
                 .filter(|&&t| t != Tab::Home)
                 .map(|t| (*t, Default::default()))
                 .collect(),
-            ..std::fs::read_to_string("save.json")
-                .ok()
-                .and_then(|s| serde_json::from_str(&s).ok())
-                .unwrap_or_else(|| {
-                    log::error!("no save found");
-                    Default::default()
-                })
+            ..save
         }
     }
 }
@@ -77,18 +149,10 @@ impl Program for Controls {
 
         match message {
             SetTab(t) => {
-                self.tab = match t {
-                    Tab::Save => {
-                        std::fs::write(
-                            "save.json",
-                            serde_json::to_string_pretty(self).expect("self not savaeable"),
-                        )
-                        .expect("couldn't save");
-
-                        Tab::Home
-                    }
-                    other => other,
+                if t == Tab::Save {
+                    self.save_tab.refresh(&self.saves);
                 }
+                self.tab = t;
             }
             Camera(msg) => {
                 self.camera_tab.update(msg);
@@ -96,6 +160,44 @@ impl Program for Controls {
             Tiling(msg) => {
                 self.tiling_tab.update(msg);
             }
+            Time(msg) => {
+                self.time_tab.update(msg);
+            }
+            Save(save::Message::Save) => {
+                let slot = self.save_tab.slot.trim().to_string();
+                let status = self
+                    .saves
+                    .save(&slot, self)
+                    .map(|()| format!("saved to {}", slot))
+                    .map_err(|e| format!("couldn't save: {}", e));
+                if status.is_ok() {
+                    self.save_tab.map = self.saves.map_path(&slot).ok().map(save::MapRequest::Save);
+                }
+                self.save_tab.report(status);
+                self.save_tab.refresh(&self.saves);
+            }
+            Save(save::Message::Load(slot)) => match self.saves.load(&slot) {
+                Ok(loaded) => {
+                    self.camera_tab = loaded.camera_tab;
+                    self.tiling_tab = loaded.tiling_tab;
+                    self.time_tab = loaded.time_tab;
+                    self.save_tab.map = self.saves.map_path(&slot).ok().map(save::MapRequest::Load);
+                    self.save_tab.slot = slot.clone();
+                    self.save_tab.report(Ok(format!("loaded {}", slot)));
+                }
+                Err(e) => self.save_tab.report(Err(format!("couldn't load: {}", e))),
+            },
+            Save(msg) => {
+                self.save_tab.update(msg);
+            }
+            Edit(msg) => {
+                self.edit_tab.update(msg);
+            }
+            MapLoaded(camera, tiling) => {
+                self.camera_tab =
+                    CameraControls::new(camera.fov, camera.height, camera.angle, camera.distance);
+                self.tiling_tab = TilingControls::new(tiling);
+            }
         };
 
         Command::none()
@@ -105,9 +207,13 @@ impl Program for Controls {
         let Self {
             camera_tab,
             tiling_tab,
+            time_tab,
+            edit_tab,
+            save_tab,
             home_button,
             tab_buttons,
             tab,
+            ..
         } = self;
 
         let content = Column::new()
@@ -134,6 +240,9 @@ impl Program for Controls {
             .push(match tab {
                 Tab::Camera => camera_tab.view().map(|msg| Message::Camera(msg)),
                 Tab::Tiling => tiling_tab.view().map(|msg| Message::Tiling(msg)),
+                Tab::Time => time_tab.view().map(|msg| Message::Time(msg)),
+                Tab::Edit => edit_tab.view().map(|msg| Message::Edit(msg)),
+                Tab::Save => save_tab.view().map(|msg| Message::Save(msg)),
                 // defaults to showing the home tab
                 _ => {
                     let mut c = Column::new().spacing(20);
@@ -0,0 +1,442 @@
+//! Saving the controls to named slots in a save directory, and loading them back.
+//!
+//! Each slot also has a map beside it, with the tiles and sprites of the hackstead,
+//! which the scene writes and reads once the controls have been saved or loaded.
+use super::{camera::CameraControls, Controls, DarkIce};
+use crate::map::{Map, MapError};
+use hexa::iced_wgpu::Renderer;
+use hexa::iced_winit::{
+    button, text_input, Align, Button, Color, Column, Command, Element, Length, Program, Row, Text,
+    TextInput,
+};
+use std::path::{Path, PathBuf};
+
+/// The slot loaded on startup, and the one saved to unless another is named.
+pub const DEFAULT_SLOT: &str = "default";
+
+/// Where saves went before there were slots, relative to the working directory.
+const LEGACY_SAVE: &str = "save.json";
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    Map {
+        path: PathBuf,
+        error: MapError,
+    },
+    /// Slot names become file names, so they're kept to letters, digits, spaces, `-` and `_`.
+    BadSlotName(String),
+}
+impl SaveError {
+    /// Whether this is just because there's nothing saved there yet.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            SaveError::Io { error, .. }
+            | SaveError::Map {
+                error: MapError::Io(error),
+                ..
+            } => error.kind() == std::io::ErrorKind::NotFound,
+            _ => false,
+        }
+    }
+}
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SaveError::Json { path, error } => {
+                write!(f, "{} is corrupt: {}", path.display(), error)
+            }
+            SaveError::Map { path, error } => write!(f, "{}: {}", path.display(), error),
+            SaveError::BadSlotName(name) => write!(
+                f,
+                "{:?} can't be a slot name, use only letters, digits, spaces, - and _",
+                name
+            ),
+        }
+    }
+}
+impl std::error::Error for SaveError {}
+
+/// A directory of save slots, each a JSON file named after the slot,
+/// with the slot's map beside it in a `.map` file.
+#[derive(Debug, Clone)]
+pub struct SaveDir {
+    pub path: PathBuf,
+}
+impl Default for SaveDir {
+    /// `$HEXA_SAVES` if it's set, otherwise `saves` in the working directory.
+    fn default() -> Self {
+        Self::new(
+            std::env::var_os("HEXA_SAVES")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| PathBuf::from("saves")),
+        )
+    }
+}
+impl SaveDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn slot_path(&self, slot: &str) -> Result<PathBuf, SaveError> {
+        self.file(slot, "json")
+    }
+
+    /// Where the map saved with a slot goes.
+    pub fn map_path(&self, slot: &str) -> Result<PathBuf, SaveError> {
+        self.file(slot, "map")
+    }
+
+    fn file(&self, slot: &str, extension: &str) -> Result<PathBuf, SaveError> {
+        let valid = |c: char| c.is_alphanumeric() || c == ' ' || c == '-' || c == '_';
+        if slot.trim().is_empty() || !slot.chars().all(valid) {
+            return Err(SaveError::BadSlotName(slot.to_string()));
+        }
+        Ok(self.path.join(format!("{}.{}", slot, extension)))
+    }
+
+    /// The names of every slot with something saved in it, sorted.
+    /// A save directory that doesn't exist yet has no slots.
+    pub fn slots(&self) -> Result<Vec<String>, SaveError> {
+        let io = |error| SaveError::Io {
+            path: self.path.clone(),
+            error,
+        };
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io(e)),
+        };
+
+        let mut slots = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(io)?.path();
+            if path.extension().map_or(false, |e| e == "json") {
+                if let Some(stem) = path.file_stem() {
+                    slots.push(stem.to_string_lossy().into_owned());
+                }
+            }
+        }
+        slots.sort();
+        Ok(slots)
+    }
+
+    /// Writes the controls to a slot, replacing whatever was there.
+    /// They're written to a temporary file that's then renamed over the slot,
+    /// so a crash halfway through never leaves a slot half written.
+    pub fn save(&self, slot: &str, controls: &Controls) -> Result<(), SaveError> {
+        let path = self.slot_path(slot)?;
+        let json = serde_json::to_string_pretty(controls).map_err(|error| SaveError::Json {
+            path: path.clone(),
+            error,
+        })?;
+
+        let tmp = path.with_extension("json.tmp");
+        std::fs::create_dir_all(&self.path)
+            .and_then(|()| std::fs::write(&tmp, json))
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .map_err(|error| SaveError::Io { path, error })
+    }
+
+    pub fn load(&self, slot: &str) -> Result<Controls, SaveError> {
+        read(&self.slot_path(slot)?)
+    }
+}
+
+fn read(path: &Path) -> Result<Controls, SaveError> {
+    let json = std::fs::read_to_string(path).map_err(|error| SaveError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    serde_json::from_str(&json).map_err(|error| SaveError::Json {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// The controls in the default slot, or failing that in the `save.json` saves used to go in.
+/// If neither exists, that's not an error, there's just nothing saved yet.
+pub fn load_default(saves: &SaveDir) -> Result<Option<Controls>, SaveError> {
+    match saves.load(DEFAULT_SLOT) {
+        Err(e) if e.is_not_found() => match read_legacy(Path::new(LEGACY_SAVE)) {
+            Err(e) if e.is_not_found() => Ok(None),
+            legacy => legacy.map(Some),
+        },
+        other => other.map(Some),
+    }
+}
+
+/// Old saves are read as version 0 maps, so they're migrated the same way as maps are.
+fn read_legacy(path: &Path) -> Result<Controls, SaveError> {
+    let map = Map::read(path).map_err(|error| SaveError::Map {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let c = map.camera;
+    Ok(Controls::with_settings(
+        CameraControls::new(c.fov, c.height, c.angle, c.distance),
+        map.tiling,
+    ))
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    SlotChanged(String),
+    Save,
+    Load(String),
+    /// The scene is done with the map in `SaveControls::map`, and this is how it went.
+    MapDone(Result<(), String>),
+}
+
+/// A map the scene should write or read, since it has the tiles.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapRequest {
+    Save(PathBuf),
+    Load(PathBuf),
+}
+
+/// The Save tab: a slot name to save to, and a button to load each slot that's been saved.
+#[derive(Debug)]
+pub struct SaveControls {
+    pub slot: String,
+    /// How the last save or load went.
+    pub status: Option<Result<String, String>>,
+    /// Set when a slot's been saved or loaded, until the scene has done the same with its map.
+    pub map: Option<MapRequest>,
+    slots: Vec<(String, button::State)>,
+    slot_input: text_input::State,
+    save_button: button::State,
+}
+impl Default for SaveControls {
+    fn default() -> Self {
+        Self {
+            slot: DEFAULT_SLOT.to_string(),
+            status: None,
+            map: None,
+            slots: Vec::new(),
+            slot_input: Default::default(),
+            save_button: Default::default(),
+        }
+    }
+}
+impl SaveControls {
+    /// Lists the slots in `saves` again, for after something's been saved there.
+    pub fn refresh(&mut self, saves: &SaveDir) {
+        match saves.slots() {
+            Ok(slots) => self.slots = slots.into_iter().map(|s| (s, Default::default())).collect(),
+            Err(e) => self.report(Err(e.to_string())),
+        }
+    }
+
+    /// Shows how a save or load went, logging it too if it failed.
+    pub fn report(&mut self, status: Result<String, String>) {
+        if let Err(e) = &status {
+            log::error!("{}", e);
+        }
+        self.status = Some(status);
+    }
+
+    /// Whether the slot name is being typed in.
+    pub fn typing(&self) -> bool {
+        self.slot_input.is_focused()
+    }
+
+    /// How the last save or load went, if anything's happened yet.
+    pub fn status_text(&self) -> Option<Text> {
+        self.status.as_ref().map(|status| match status {
+            Ok(msg) => Text::new(msg).size(18),
+            Err(e) => Text::new(e).size(18).color(Color {
+                r: 1.0,
+                g: 0.45,
+                b: 0.4,
+                a: 1.0,
+            }),
+        })
+    }
+}
+
+impl Program for SaveControls {
+    type Renderer = Renderer;
+    type Message = Message;
+
+    /// Only keeps track of the slot name and the map; saving and loading change all of the
+    /// controls, so they're done by Controls::update.
+    fn update(&mut self, message: Message) -> Command<Message> {
+        match message {
+            Message::SlotChanged(slot) => self.slot = slot,
+            Message::MapDone(status) => {
+                self.map = None;
+                if let Err(e) = status {
+                    self.report(Err(e));
+                }
+            }
+            _ => {}
+        }
+
+        Command::none()
+    }
+
+    fn view(&mut self) -> Element<Message, Renderer> {
+        let status = self.status_text();
+
+        let mut slots = Column::new().spacing(10);
+        for (slot, button) in &mut self.slots {
+            slots = slots.push(
+                Button::new(button, Text::new(format!("Load {}", slot)).size(18))
+                    .width(Length::Fill)
+                    .on_press(Message::Load(slot.clone()))
+                    .style(DarkIce),
+            );
+        }
+
+        let mut c = Column::new()
+            .spacing(25)
+            .padding(10)
+            .push(
+                Row::new()
+                    .spacing(10)
+                    .align_items(Align::Center)
+                    .push(
+                        TextInput::new(
+                            &mut self.slot_input,
+                            "Slot",
+                            &self.slot,
+                            Message::SlotChanged,
+                        )
+                        .padding(5)
+                        .on_submit(Message::Save),
+                    )
+                    .push(
+                        Button::new(&mut self.save_button, Text::new("Save").size(18))
+                            .padding(5)
+                            .on_press(Message::Save)
+                            .style(DarkIce),
+                    ),
+            )
+            .push(slots);
+        if let Some(status) = status {
+            c = c.push(status);
+        }
+        c.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty save directory of its own for each test, under the system's temporary one.
+    fn saves(test: &str) -> SaveDir {
+        let path = std::env::temp_dir().join(format!("hexa-saves-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        SaveDir::new(path)
+    }
+
+    fn seeded(seed: u32) -> Controls {
+        let mut controls = Controls::default();
+        controls.tiling_tab.data.seed = seed;
+        controls
+    }
+
+    #[test]
+    fn saved_slots_load() {
+        let saves = saves("load");
+        saves.save("one", &seeded(7)).unwrap();
+        saves.save("two", &seeded(8)).unwrap();
+        saves.save("one", &seeded(9)).unwrap();
+
+        assert_eq!(saves.load("one").unwrap().tiling_tab.data.seed, 9);
+        assert_eq!(saves.load("two").unwrap().tiling_tab.data.seed, 8);
+        assert!(!saves.path.join("one.json.tmp").exists());
+
+        std::fs::remove_dir_all(&saves.path).unwrap();
+    }
+
+    #[test]
+    fn the_default_slot_is_loaded_first() {
+        let saves = saves("default");
+        saves.save(DEFAULT_SLOT, &seeded(3)).unwrap();
+        let loaded = load_default(&saves).unwrap().unwrap();
+        assert_eq!(loaded.tiling_tab.data.seed, 3);
+
+        std::fs::remove_dir_all(&saves.path).unwrap();
+    }
+
+    #[test]
+    fn the_default_map_is_loaded_on_startup() {
+        let saves = saves("startup");
+        assert_eq!(Controls::open(saves.clone()).save_tab.map, None);
+
+        saves.save(DEFAULT_SLOT, &seeded(5)).unwrap();
+        let map = saves.map_path(DEFAULT_SLOT).unwrap();
+        std::fs::write(&map, b"HXMP").unwrap();
+
+        let controls = Controls::open(saves.clone());
+        assert_eq!(controls.tiling_tab.data.seed, 5);
+        assert_eq!(controls.save_tab.map, Some(MapRequest::Load(map)));
+
+        std::fs::remove_dir_all(&saves.path).unwrap();
+    }
+
+    #[test]
+    fn missing_and_corrupt_slots_fail() {
+        let saves = saves("corrupt");
+        assert!(saves.load("nothing").unwrap_err().is_not_found());
+
+        std::fs::create_dir_all(&saves.path).unwrap();
+        std::fs::write(saves.path.join("broken.json"), "{ not json").unwrap();
+        match saves.load("broken") {
+            Err(SaveError::Json { path, .. }) => assert_eq!(path, saves.path.join("broken.json")),
+            other => panic!("expected a JSON error, got {:?}", other.map(|_| ())),
+        }
+
+        std::fs::remove_dir_all(&saves.path).unwrap();
+    }
+
+    #[test]
+    fn slots_are_the_saved_json_files() {
+        let saves = saves("slots");
+        assert_eq!(saves.slots().unwrap(), Vec::<String>::new());
+
+        saves.save("b", &Controls::default()).unwrap();
+        saves.save("a", &Controls::default()).unwrap();
+        std::fs::write(saves.map_path("a").unwrap(), b"HXMP").unwrap();
+        std::fs::write(saves.path.join("notes.txt"), "").unwrap();
+        assert_eq!(
+            saves.slots().unwrap(),
+            vec!["a".to_string(), "b".to_string()]
+        );
+
+        std::fs::remove_dir_all(&saves.path).unwrap();
+    }
+
+    #[test]
+    fn slot_names_are_checked() {
+        let saves = SaveDir::new("saves");
+        assert_eq!(
+            saves.slot_path("my save-2_b").unwrap(),
+            Path::new("saves").join("my save-2_b.json")
+        );
+        assert_eq!(
+            saves.map_path("default").unwrap(),
+            Path::new("saves").join("default.map")
+        );
+
+        for bad in &["", "   ", "../up", "a/b", "a\\b", "dot.json", "star*"] {
+            match saves.slot_path(bad) {
+                Err(SaveError::BadSlotName(name)) => assert_eq!(name, *bad),
+                other => panic!("{:?} should be refused, got {:?}", bad, other),
+            }
+            assert!(saves.map_path(bad).is_err());
+            assert!(saves.save(bad, &Controls::default()).is_err());
+        }
+    }
+}
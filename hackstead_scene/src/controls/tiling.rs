@@ -1,12 +1,24 @@
+use super::DarkIce;
+use crate::terrain::Generator;
 use hexa::iced_wgpu::Renderer;
-use hexa::iced_winit::{slider, Align, Column, Command, Element, Program, Slider, Text};
+use hexa::iced_winit::{
+    button, slider, Align, Button, Checkbox, Column, Command, Element, Program, Radio, Slider, Text,
+};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ElevationChanged(f32),
+    SeaLevelChanged(f32),
+    GeneratorPicked(Generator),
+    OctavesChanged(u32),
     SizeChanged(u32),
     SeedChanged(u32),
+    InfiniteToggled(bool),
+    /// Puts all of the settings back how they were, for undoing and redoing.
+    Restored(Data),
     Retiled,
+    ExportHeightmap,
+    HeightmapExported,
 }
 
 fn dirty_default() -> bool {
@@ -17,16 +29,28 @@ fn dirty_default() -> bool {
 pub struct TilingControls {
     #[serde(skip, default = "dirty_default")]
     pub dirty: bool,
+    /// Set when the heights of the map should be written out to an image.
+    #[serde(skip)]
+    pub export: bool,
     #[serde(skip)]
     sliders: Sliders,
+    #[serde(skip)]
+    export_button: button::State,
     pub data: Data,
 }
 impl Default for TilingControls {
     fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+impl TilingControls {
+    pub fn new(data: Data) -> Self {
         Self {
             dirty: true,
+            export: false,
             sliders: Default::default(),
-            data: Default::default(),
+            export_button: Default::default(),
+            data,
         }
     }
 }
@@ -41,15 +65,33 @@ impl std::ops::Deref for TilingControls {
 #[derive(Default, Debug)]
 struct Sliders {
     elevation: slider::State,
+    sea_level: slider::State,
+    octaves: slider::State,
     size: slider::State,
     seed: slider::State,
 }
 
-#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct Data {
     pub elevation: f32,
     pub size: u32,
     pub seed: u32,
+    /// Keep generating chunks wherever the camera goes, instead of stopping at `size`.
+    /// When this is on, `size` only affects how spread out the hills are.
+    #[serde(default)]
+    pub infinite: bool,
+    /// How high the water comes up. Anywhere the ground is lower than this is underwater.
+    #[serde(default)]
+    pub sea_level: f32,
+    /// Which TerrainGenerator shapes the ground.
+    #[serde(default)]
+    pub generator: Generator,
+    /// How many layers of noise the fractal generators pile up.
+    #[serde(default = "octaves_default")]
+    pub octaves: u32,
+}
+fn octaves_default() -> u32 {
+    4
 }
 impl Default for Data {
     fn default() -> Self {
@@ -57,6 +99,10 @@ impl Default for Data {
             elevation: 0.1,
             size: 5,
             seed: 42,
+            infinite: false,
+            sea_level: 0.0,
+            generator: Generator::Perlin,
+            octaves: octaves_default(),
         }
     }
 }
@@ -73,6 +119,18 @@ impl Program for TilingControls {
                 self.data.elevation = e;
                 self.dirty = true;
             }
+            SeaLevelChanged(sea_level) => {
+                self.data.sea_level = sea_level;
+                self.dirty = true;
+            }
+            GeneratorPicked(generator) => {
+                self.data.generator = generator;
+                self.dirty = true;
+            }
+            OctavesChanged(octaves) => {
+                self.data.octaves = octaves;
+                self.dirty = true;
+            }
             SizeChanged(size) => {
                 self.data.size = size;
                 self.dirty = true;
@@ -81,9 +139,23 @@ impl Program for TilingControls {
                 self.data.seed = seed;
                 self.dirty = true;
             }
+            InfiniteToggled(infinite) => {
+                self.data.infinite = infinite;
+                self.dirty = true;
+            }
+            Restored(data) => {
+                self.data = data;
+                self.dirty = true;
+            }
             Retiled => {
                 self.dirty = false;
             }
+            ExportHeightmap => {
+                self.export = true;
+            }
+            HeightmapExported => {
+                self.export = false;
+            }
         }
 
         Command::none()
@@ -92,9 +164,12 @@ impl Program for TilingControls {
     fn view(&mut self) -> Element<Message, Renderer> {
         let Sliders {
             elevation,
+            sea_level,
+            octaves,
             size,
             seed,
         } = &mut self.sliders;
+        let export_button = &mut self.export_button;
         let data = self.data.clone();
 
         let labeled_slider = |label, slider| {
@@ -105,15 +180,42 @@ impl Program for TilingControls {
                 .push(slider)
         };
 
-        Column::new()
+        let generators = Generator::ALL
+            .iter()
+            .fold(Column::new().spacing(5), |c, &g| {
+                c.push(Radio::new(
+                    g,
+                    format!("{:?}", g),
+                    Some(data.generator),
+                    Message::GeneratorPicked,
+                ))
+            });
+
+        let mut column = Column::new()
             .spacing(25)
             .padding(10)
+            .push(generators)
             .push(labeled_slider(
                 "Elevation",
                 Slider::new(elevation, 0.0..=3.0, data.elevation, move |elevation| {
                     Message::ElevationChanged(elevation)
                 }),
             ))
+            .push(labeled_slider(
+                "Sea Level",
+                Slider::new(sea_level, -3.0..=3.0, data.sea_level, move |sea_level| {
+                    Message::SeaLevelChanged(sea_level)
+                }),
+            ));
+        if data.generator.has_octaves() {
+            column = column.push(labeled_slider(
+                "Octaves",
+                Slider::new(octaves, 1.0..=8.0, data.octaves as f32, move |octaves| {
+                    Message::OctavesChanged(octaves as u32)
+                }),
+            ));
+        }
+        column
             .push(labeled_slider(
                 "Size",
                 Slider::new(size, 1.0..=15.0, data.size as f32, move |size| {
@@ -126,6 +228,17 @@ impl Program for TilingControls {
                     Message::SeedChanged(seed as u32)
                 }),
             ))
+            .push(Checkbox::new(
+                data.infinite,
+                "Infinite",
+                Message::InfiniteToggled,
+            ))
+            .push(
+                Button::new(export_button, Text::new("Export Heightmap").size(18))
+                    .padding(5)
+                    .on_press(Message::ExportHeightmap)
+                    .style(DarkIce),
+            )
             .into()
     }
 }
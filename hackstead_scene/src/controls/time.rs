@@ -0,0 +1,102 @@
+use hexa::iced_wgpu::Renderer;
+use hexa::iced_winit::{slider, Align, Checkbox, Column, Command, Element, Program, Slider, Text};
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    HourChanged(f32),
+    DayLengthChanged(f32),
+    PausedToggled(bool),
+    /// This many seconds of real time have passed.
+    Elapsed(f32),
+}
+
+#[derive(Default, Debug)]
+struct Sliders {
+    hour: slider::State,
+    day_length: slider::State,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct TimeControls {
+    /// The time of day, from 0.0 at midnight up to 24.0.
+    pub hour: f32,
+    /// How many minutes of real time a whole day takes.
+    pub day_length: f32,
+    pub paused: bool,
+    #[serde(skip)]
+    sliders: Sliders,
+}
+
+impl Default for TimeControls {
+    fn default() -> Self {
+        Self::new(14.0, 20.0, false)
+    }
+}
+
+impl TimeControls {
+    pub fn new(hour: f32, day_length: f32, paused: bool) -> Self {
+        Self {
+            hour,
+            day_length,
+            paused,
+            sliders: Default::default(),
+        }
+    }
+}
+
+impl Program for TimeControls {
+    type Renderer = Renderer;
+    type Message = Message;
+
+    fn update(&mut self, message: Message) -> Command<Message> {
+        use Message::*;
+
+        match message {
+            HourChanged(hour) => self.hour = hour,
+            DayLengthChanged(day_length) => self.day_length = day_length,
+            PausedToggled(paused) => self.paused = paused,
+            Elapsed(seconds) => {
+                if !self.paused && self.day_length > 0.0 {
+                    let hours = seconds / 60.0 / self.day_length * 24.0;
+                    self.hour = (self.hour + hours).rem_euclid(24.0);
+                }
+            }
+        }
+
+        Command::none()
+    }
+
+    fn view(&mut self) -> Element<Message, Renderer> {
+        let Sliders { hour, day_length } = &mut self.sliders;
+
+        let labeled_slider = |label, slider| {
+            Column::new()
+                .spacing(2)
+                .align_items(Align::Center)
+                .push(Text::new(label).size(20))
+                .push(slider)
+        };
+
+        Column::new()
+            .spacing(25)
+            .padding(10)
+            .push(labeled_slider(
+                format!(
+                    "Time {:02}:{:02}",
+                    self.hour as u32,
+                    (self.hour.fract() * 60.0) as u32
+                ),
+                Slider::new(hour, 0.0..=24.0, self.hour, move |h| {
+                    Message::HourChanged(h)
+                }),
+            ))
+            .push(labeled_slider(
+                format!("Day Length ({:.0} minutes)", self.day_length),
+                Slider::new(day_length, 1.0..=60.0, self.day_length, move |d| {
+                    Message::DayLengthChanged(d)
+                }),
+            ))
+            .push(Checkbox::new(self.paused, "Paused", Message::PausedToggled))
+            .into()
+    }
+}
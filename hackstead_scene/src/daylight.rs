@@ -0,0 +1,128 @@
+//! What the sun, the sky and the light look like at each time of day.
+use hexa::{iced_winit::Color, na::Vector3, Lighting, Sky};
+
+const NOON_SUN: Color = Color {
+    r: 0.75,
+    g: 0.72,
+    b: 0.66,
+    a: 1.0,
+};
+const DAWN_SUN: Color = Color {
+    r: 1.0,
+    g: 0.55,
+    b: 0.3,
+    a: 1.0,
+};
+const MOON: Color = Color {
+    r: 0.18,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+const DAY_AMBIENT: Color = Color {
+    r: 0.38,
+    g: 0.4,
+    b: 0.45,
+    a: 1.0,
+};
+const NIGHT_AMBIENT: Color = Color {
+    r: 0.08,
+    g: 0.1,
+    b: 0.18,
+    a: 1.0,
+};
+const DAY_ZENITH: Color = Color {
+    r: 0.25,
+    g: 0.45,
+    b: 0.8,
+    a: 1.0,
+};
+const DAY_HORIZON: Color = Color {
+    r: 0.65,
+    g: 0.78,
+    b: 0.9,
+    a: 1.0,
+};
+const NIGHT_ZENITH: Color = Color {
+    r: 0.01,
+    g: 0.02,
+    b: 0.06,
+    a: 1.0,
+};
+const NIGHT_HORIZON: Color = Color {
+    r: 0.05,
+    g: 0.06,
+    b: 0.12,
+    a: 1.0,
+};
+const DUSK_HORIZON: Color = Color {
+    r: 0.95,
+    g: 0.5,
+    b: 0.3,
+    a: 1.0,
+};
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    Color {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+        a: lerp(a.a, b.a),
+    }
+}
+
+fn dim(c: Color, brightness: f32) -> Color {
+    Color {
+        r: c.r * brightness,
+        g: c.g * brightness,
+        b: c.b * brightness,
+        ..c
+    }
+}
+
+/// Which way the sun is from the ground at this hour, from 0.0 at midnight up to 24.0.
+/// It rises in the east at six, is at its highest at noon, and sets in the west at six.
+pub fn sun_position(hour: f32) -> Vector3<f32> {
+    let angle = (hour - 6.0) / 12.0 * std::f32::consts::PI;
+    // leaning a little to the south, so it's never quite straight overhead
+    Vector3::new(angle.cos(), -0.35, angle.sin()).normalize()
+}
+
+/// The lighting and sky at this hour.
+pub fn at(hour: f32) -> (Lighting, Sky) {
+    let sun = sun_position(hour);
+    let height = sun.z;
+    let day = smoothstep(-0.15, 0.15, height);
+
+    // The moon is opposite the sun, and takes over casting shadows once the sun has set.
+    // Neither gives off any light right at the horizon, so nothing jumps when they swap.
+    let (sun_direction, sun_color) = if height >= 0.0 {
+        let color = mix(DAWN_SUN, NOON_SUN, smoothstep(0.0, 0.5, height));
+        (-sun, dim(color, smoothstep(0.0, 0.2, height)))
+    } else {
+        (sun, dim(MOON, smoothstep(0.0, 0.2, -height)))
+    };
+    let twilight = 1.0 - smoothstep(0.0, 0.3, height.abs());
+
+    let lighting = Lighting {
+        sun_direction,
+        sun_color,
+        ambient: mix(NIGHT_AMBIENT, DAY_AMBIENT, day),
+        ..Lighting::default()
+    };
+    let sky = Sky {
+        zenith: mix(NIGHT_ZENITH, DAY_ZENITH, day),
+        horizon: mix(
+            mix(NIGHT_HORIZON, DAY_HORIZON, day),
+            DUSK_HORIZON,
+            twilight * 0.6,
+        ),
+    };
+    (lighting, sky)
+}
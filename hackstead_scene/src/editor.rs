@@ -0,0 +1,253 @@
+//! Shaping the ground by hand, one column at a time.
+use hexa::coord::{Axial, Offset};
+use hexa::{Sprite, TextureId, Tile};
+
+/// How far raising or lowering moves the ground, in world units.
+const STEP: f32 = 0.1;
+/// Lowering the ground stops when its bottom tile gets this short.
+const MIN_BUTT_SIZE: f32 = 0.05;
+/// How tall the tiles stacked by Add Layer are. The top tile can't just be copied,
+/// since if it's the only one it reaches all the way down to the bottom of the world.
+const LAYER_THICKNESS: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Raise,
+    Lower,
+    /// Stacks a thin tile with the same textures as the top one on top of it.
+    AddLayer,
+    /// Takes the top tile off, unless it's the only one left.
+    RemoveLayer,
+    /// Gives the top tile the texture picked from the palette.
+    PaintHat,
+    PaintButt,
+    /// Puts a stump on the tile clicked, or takes away the sprite already there.
+    /// Unlike the other tools, this ignores the brush size.
+    PlaceSprite,
+}
+impl Tool {
+    pub const ALL: &'static [Tool] = &[
+        Tool::Raise,
+        Tool::Lower,
+        Tool::AddLayer,
+        Tool::RemoveLayer,
+        Tool::PaintHat,
+        Tool::PaintButt,
+        Tool::PlaceSprite,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Tool::Raise => "Raise",
+            Tool::Lower => "Lower",
+            Tool::AddLayer => "Add Layer",
+            Tool::RemoveLayer => "Remove Layer",
+            Tool::PaintHat => "Paint Hat",
+            Tool::PaintButt => "Paint Butt",
+            Tool::PlaceSprite => "Place Sprite",
+        }
+    }
+
+    /// Uses this tool on a column, painting with `texture` if it's a paint tool, and returns
+    /// whether that changed anything, so edits that do nothing aren't worth undoing.
+    /// Columns are never left empty, and tools that don't change tiles leave them alone.
+    pub fn apply(self, column: &mut Vec<Tile>, texture: TextureId) -> bool {
+        if column.is_empty() {
+            return false;
+        }
+        let top = column.len() - 1;
+
+        match self {
+            Tool::Raise => raise(column, STEP),
+            Tool::Lower => {
+                let by = STEP.min(column[0].butt_size - MIN_BUTT_SIZE).max(0.0);
+                if by <= 0.0 {
+                    return false;
+                }
+                raise(column, -by);
+            }
+            Tool::AddLayer => {
+                column.push(Tile {
+                    butt_size: LAYER_THICKNESS,
+                    ..column[top].clone()
+                });
+            }
+            Tool::RemoveLayer => {
+                if column.len() == 1 {
+                    return false;
+                }
+                column.pop();
+            }
+            Tool::PaintHat => return paint(&mut column[top].hat, texture),
+            Tool::PaintButt => return paint(&mut column[top].butt, texture),
+            Tool::PlaceSprite => return false,
+        }
+        true
+    }
+}
+impl Default for Tool {
+    fn default() -> Self {
+        Tool::Raise
+    }
+}
+
+/// The column at `center` and every one within `rings` of it.
+pub fn brush(center: Offset, rings: u32) -> impl Iterator<Item = Offset> {
+    center
+        .to_axial()
+        .spiral(rings)
+        .into_iter()
+        .map(|a| a.to_offset())
+}
+
+/// Takes away any sprites standing on the tile at `p`, or if there aren't any,
+/// stands one there made of `image`.
+pub fn toggle_sprite(sprites: &mut Vec<Sprite>, p: Offset, image: TextureId) {
+    let before = sprites.len();
+    sprites.retain(|s| Axial::from_world(s.position).to_offset() != p);
+    if sprites.len() == before {
+        sprites.push(Sprite {
+            image,
+            position: p.to_world(),
+            scale: hexa::na::Vector2::repeat(1.0),
+        });
+    }
+}
+
+/// Swaps in `texture`, returning whether it wasn't there already.
+fn paint(painted: &mut TextureId, texture: TextureId) -> bool {
+    std::mem::replace(painted, texture) != texture
+}
+
+/// Moves the whole column up by `by`, stretching its bottom tile so it still reaches as far down.
+fn raise(column: &mut [Tile], by: f32) {
+    for tile in column.iter_mut() {
+        tile.elevation += by;
+    }
+    column[0].butt_size += by;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(elevation: f32, butt_size: f32) -> Tile {
+        Tile {
+            position: Offset::new(3, 4),
+            elevation,
+            butt_size,
+            hat: TextureId(1),
+            butt: TextureId(2),
+            highlight: None,
+        }
+    }
+
+    fn tops(column: &[Tile]) -> Vec<f32> {
+        Tile::stack_elevations(column).collect()
+    }
+
+    #[test]
+    fn raise_and_lower_keep_the_bottom_in_place() {
+        let mut column = vec![tile(1.0, 1.0), tile(1.0, 0.5)];
+        assert!(Tool::Raise.apply(&mut column, TextureId(0)));
+        assert!((column[0].elevation - 1.1).abs() < 1e-6);
+        assert!((column[0].butt_size - 1.1).abs() < 1e-6);
+        assert!((tops(&column)[1] - 1.6).abs() < 1e-6);
+
+        assert!(Tool::Lower.apply(&mut column, TextureId(0)));
+        assert!((column[0].elevation - 1.0).abs() < 1e-6);
+        assert!((column[0].butt_size - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn lowering_stops_at_the_shortest_butt() {
+        let mut column = vec![tile(1.0, 0.08)];
+        assert!(Tool::Lower.apply(&mut column, TextureId(0)));
+        assert!((column[0].butt_size - MIN_BUTT_SIZE).abs() < 1e-6);
+        assert!(!Tool::Lower.apply(&mut column, TextureId(0)));
+        assert!((column[0].butt_size - MIN_BUTT_SIZE).abs() < 1e-6);
+        assert!((column[0].elevation - (1.0 - 0.08 + MIN_BUTT_SIZE)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn added_layers_are_thin() {
+        let mut column = vec![tile(1.0, 100.0)];
+        assert!(Tool::AddLayer.apply(&mut column, TextureId(0)));
+        assert_eq!(column.len(), 2);
+        assert_eq!(column[1].butt_size, LAYER_THICKNESS);
+        assert_eq!(column[1].hat, TextureId(1));
+        assert!((tops(&column)[1] - (1.0 + LAYER_THICKNESS)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn the_last_layer_is_never_removed() {
+        let mut column = vec![tile(1.0, 1.0), tile(1.0, 0.5)];
+        assert!(Tool::RemoveLayer.apply(&mut column, TextureId(0)));
+        assert_eq!(column.len(), 1);
+        assert!(!Tool::RemoveLayer.apply(&mut column, TextureId(0)));
+        assert_eq!(column.len(), 1);
+    }
+
+    #[test]
+    fn painting_changes_the_top_tile() {
+        let mut column = vec![tile(1.0, 1.0), tile(1.0, 0.5)];
+        assert!(Tool::PaintHat.apply(&mut column, TextureId(7)));
+        assert!(Tool::PaintButt.apply(&mut column, TextureId(8)));
+        assert!(!Tool::PaintHat.apply(&mut column, TextureId(7)));
+        assert!(!Tool::PaintButt.apply(&mut column, TextureId(8)));
+        assert_eq!(
+            (column[1].hat, column[1].butt),
+            (TextureId(7), TextureId(8))
+        );
+        assert_eq!(
+            (column[0].hat, column[0].butt),
+            (TextureId(1), TextureId(2))
+        );
+    }
+
+    #[test]
+    fn empty_columns_and_sprites_are_left_alone() {
+        for &tool in Tool::ALL {
+            let mut column = Vec::new();
+            assert!(!tool.apply(&mut column, TextureId(0)));
+            assert!(column.is_empty());
+        }
+
+        let mut column = vec![tile(1.0, 1.0)];
+        assert!(!Tool::PlaceSprite.apply(&mut column, TextureId(0)));
+        assert_eq!(column.len(), 1);
+        assert_eq!(column[0].elevation, 1.0);
+    }
+
+    #[test]
+    fn brushes_cover_every_column_in_range() {
+        let center = Offset::new(5, 3);
+        assert_eq!(brush(center, 0).collect::<Vec<_>>(), vec![center]);
+
+        for rings in 1..4 {
+            let covered = brush(center, rings).collect::<Vec<_>>();
+            let unique = covered.iter().collect::<std::collections::HashSet<_>>();
+            assert_eq!(covered.len(), 1 + 3 * rings as usize * (rings as usize + 1));
+            assert_eq!(unique.len(), covered.len());
+            assert!(covered
+                .iter()
+                .all(|p| p.to_axial().distance(center.to_axial()) <= rings));
+        }
+    }
+
+    #[test]
+    fn sprites_toggle() {
+        let (here, there) = (Offset::new(2, 2), Offset::new(6, 1));
+        let mut sprites = Vec::new();
+
+        toggle_sprite(&mut sprites, here, TextureId(3));
+        toggle_sprite(&mut sprites, there, TextureId(3));
+        assert_eq!(sprites.len(), 2);
+        assert_eq!(sprites[0].image, TextureId(3));
+        assert_eq!(Axial::from_world(sprites[0].position).to_offset(), here);
+
+        toggle_sprite(&mut sprites, here, TextureId(3));
+        assert_eq!(sprites.len(), 1);
+        assert_eq!(Axial::from_world(sprites[0].position).to_offset(), there);
+    }
+}
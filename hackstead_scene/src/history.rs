@@ -0,0 +1,286 @@
+//! Undoing and redoing edits, by remembering how things were before and after each one.
+use crate::controls::{camera, tiling};
+use hexa::coord::Offset;
+use hexa::{Sprite, Tile};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many edits can be undone before the oldest are forgotten.
+pub const LIMIT: usize = 100;
+
+/// Dragging a slider changes it every frame, so changes to the same settings this close
+/// together are undone all at once.
+const MERGE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Something that was changed, and how it was before and after.
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// Columns of tiles replaced by the editor.
+    Tiles {
+        before: Vec<(Offset, Vec<Tile>)>,
+        after: Vec<(Offset, Vec<Tile>)>,
+    },
+    Sprites {
+        before: Vec<Sprite>,
+        after: Vec<Sprite>,
+    },
+    Camera {
+        before: camera::Settings,
+        after: camera::Settings,
+    },
+    /// Changes to the tiling controls regenerate the world, but columns edited by hand
+    /// are kept through that, so undoing one brings back the world as it was.
+    Tiling {
+        before: tiling::Data,
+        after: tiling::Data,
+    },
+}
+impl Command {
+    /// The command that takes things back to how they were before this one.
+    pub fn inverse(self) -> Command {
+        use Command::*;
+
+        match self {
+            Tiles { before, after } => Tiles {
+                before: after,
+                after: before,
+            },
+            Sprites { before, after } => Sprites {
+                before: after,
+                after: before,
+            },
+            Camera { before, after } => Camera {
+                before: after,
+                after: before,
+            },
+            Tiling { before, after } => Tiling {
+                before: after,
+                after: before,
+            },
+        }
+    }
+
+    /// Folds a later change to the same settings into this one,
+    /// handing it back if it changed something else.
+    fn merge(&mut self, later: Command) -> Option<Command> {
+        match (self, later) {
+            (Command::Camera { after, .. }, Command::Camera { after: later, .. }) => {
+                *after = later;
+                None
+            }
+            (Command::Tiling { after, .. }, Command::Tiling { after: later, .. }) => {
+                *after = later;
+                None
+            }
+            (_, later) => Some(later),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Undo,
+    Redo,
+}
+
+/// The edits that can be undone, oldest first, and those that have been undone and can be redone.
+#[derive(Debug)]
+pub struct History {
+    limit: usize,
+    /// Each with when it was last recorded, or None if it was redone and shouldn't be merged into.
+    undo: VecDeque<(Command, Option<Instant>)>,
+    redo: Vec<Command>,
+}
+impl Default for History {
+    fn default() -> Self {
+        Self::new(LIMIT)
+    }
+}
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Remembers an edit that's just been made, forgetting anything that was undone.
+    pub fn record(&mut self, command: Command) {
+        self.record_at(command, Instant::now());
+    }
+
+    fn record_at(&mut self, command: Command, now: Instant) {
+        self.redo.clear();
+
+        let command = match self.undo.back_mut() {
+            Some((last, Some(at))) if now.duration_since(*at) < MERGE_WINDOW => {
+                match last.merge(command) {
+                    None => {
+                        *at = now;
+                        return;
+                    }
+                    Some(command) => command,
+                }
+            }
+            _ => command,
+        };
+
+        self.undo.push_back((command, Some(now)));
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// The command that undoes or redoes the last edit, if there's one to undo or redo.
+    /// Applying it isn't an edit of its own, so it shouldn't be recorded.
+    pub fn step(&mut self, step: Step) -> Option<Command> {
+        match step {
+            Step::Undo => {
+                let (command, _) = self.undo.pop_back()?;
+                self.redo.push(command.clone());
+                Some(command.inverse())
+            }
+            Step::Redo => {
+                let command = self.redo.pop()?;
+                self.undo.push_back((command.clone(), None));
+                Some(command)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(fov: f32) -> camera::Settings {
+        camera::Settings {
+            fov,
+            height: 3.0,
+            angle: 0.0,
+            distance: 6.0,
+        }
+    }
+
+    fn turn(from: f32, to: f32) -> Command {
+        Command::Camera {
+            before: camera(from),
+            after: camera(to),
+        }
+    }
+
+    /// The fov a camera command leaves the camera with.
+    fn fov(command: Option<Command>) -> Option<f32> {
+        match command? {
+            Command::Camera { after, .. } => Some(after.fov),
+            other => panic!("expected a camera command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn changes_close_together_are_undone_at_once() {
+        let mut history = History::default();
+        let start = Instant::now();
+        history.record_at(turn(1.0, 2.0), start);
+        history.record_at(turn(2.0, 3.0), start + MERGE_WINDOW / 2);
+        history.record_at(turn(3.0, 4.0), start + MERGE_WINDOW);
+
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+        assert_eq!(fov(history.step(Step::Undo)), None);
+    }
+
+    #[test]
+    fn changes_far_apart_are_undone_one_at_a_time() {
+        let mut history = History::default();
+        let start = Instant::now();
+        history.record_at(turn(1.0, 2.0), start);
+        history.record_at(turn(2.0, 3.0), start + MERGE_WINDOW * 2);
+
+        assert_eq!(fov(history.step(Step::Undo)), Some(2.0));
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+    }
+
+    #[test]
+    fn different_settings_are_not_merged() {
+        let mut history = History::default();
+        let now = Instant::now();
+        history.record_at(turn(1.0, 2.0), now);
+        history.record_at(
+            Command::Tiling {
+                before: Default::default(),
+                after: tiling::Data {
+                    seed: 7,
+                    ..Default::default()
+                },
+            },
+            now,
+        );
+
+        match history.step(Step::Undo) {
+            Some(Command::Tiling { after, .. }) => assert_eq!(after, Default::default()),
+            other => panic!("expected a tiling command, got {:?}", other),
+        }
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+    }
+
+    #[test]
+    fn redone_changes_are_not_merged_into() {
+        let mut history = History::default();
+        let now = Instant::now();
+        history.record_at(turn(1.0, 2.0), now);
+        history.step(Step::Undo);
+        history.step(Step::Redo);
+        history.record_at(turn(2.0, 3.0), now);
+
+        assert_eq!(fov(history.step(Step::Undo)), Some(2.0));
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+    }
+
+    #[test]
+    fn only_the_latest_edits_are_kept() {
+        let mut history = History::default();
+        let start = Instant::now();
+        for i in 0..LIMIT + 10 {
+            let fov = i as f32;
+            history.record_at(turn(fov, fov + 1.0), start + MERGE_WINDOW * i as u32);
+        }
+
+        let mut undone = 0;
+        let mut last = None;
+        while let Some(command) = history.step(Step::Undo) {
+            undone += 1;
+            last = fov(Some(command));
+        }
+        assert_eq!(undone, LIMIT);
+        assert_eq!(last, Some(10.0));
+    }
+
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut history = History::default();
+        let start = Instant::now();
+        history.record_at(turn(1.0, 2.0), start);
+        history.step(Step::Undo);
+        history.record_at(turn(1.0, 5.0), start + MERGE_WINDOW * 2);
+
+        assert_eq!(fov(history.step(Step::Redo)), None);
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+    }
+
+    #[test]
+    fn undo_then_redo_round_trips() {
+        let mut history = History::default();
+        let start = Instant::now();
+        history.record_at(turn(1.0, 2.0), start);
+        history.record_at(turn(2.0, 3.0), start + MERGE_WINDOW * 2);
+
+        assert_eq!(fov(history.step(Step::Undo)), Some(2.0));
+        assert_eq!(fov(history.step(Step::Undo)), Some(1.0));
+        assert_eq!(fov(history.step(Step::Undo)), None);
+        assert_eq!(fov(history.step(Step::Redo)), Some(2.0));
+        assert_eq!(fov(history.step(Step::Redo)), Some(3.0));
+        assert_eq!(fov(history.step(Step::Redo)), None);
+        assert_eq!(fov(history.step(Step::Undo)), Some(2.0));
+    }
+}
@@ -1,11 +1,29 @@
-use hexa::{camera::Camera, Renderer, Scene, Sprite, Tile};
+use coord::Offset;
+use hexa::{
+    camera::Camera, coord, pick, Highlight, RenderStats, Renderer, Scene, Sprite, TextureId, Tile,
+};
 use hexa::{iced_wgpu, iced_winit};
 use iced_wgpu::Primitive as GuiPrimitive;
 use iced_winit::{mouse, program, winit};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
 use winit::event::{ModifiersState, WindowEvent};
 
-mod controls;
+pub mod controls;
 use controls::Controls;
+mod daylight;
+pub mod editor;
+pub mod history;
+use history::{Command, History, Step};
+pub mod map;
+use map::{Map, MapCamera, MapError, MapSprite, MapTile};
+pub mod terrain;
+use terrain::{Biomes, TerrainGenerator};
+mod world;
+use world::World;
+
+/// How far the arrow keys move the camera, in world units.
+const PAN_STEP: f32 = 1.5;
 
 #[no_mangle]
 pub fn _scene_init(r: &mut dyn Renderer) -> *mut dyn Scene {
@@ -13,22 +31,296 @@ pub fn _scene_init(r: &mut dyn Renderer) -> *mut dyn Scene {
     Box::into_raw(Box::new(HacksteadScene::new(r)))
 }
 
+/// The textures this scene's sprites are made of, looked up by name once.
+/// Tile textures are named in the biome table instead, see `terrain::biome`.
+#[derive(Debug, Clone, Copy)]
+struct Textures {
+    stump: TextureId,
+}
+impl Textures {
+    fn new(r: &dyn Renderer) -> Self {
+        let sprite = |name| {
+            r.sprite_texture(name).unwrap_or_else(|| {
+                log::error!("no texture named {} was loaded", name);
+                TextureId::default()
+            })
+        };
+
+        Self {
+            stump: sprite("stump"),
+        }
+    }
+}
+
 pub struct HacksteadScene {
     gui: program::State<Controls>,
     camera: Camera,
+    textures: Textures,
+    /// Which tiles the ground is made of where.
+    biomes: Biomes,
+    /// Shapes the ground, rebuilt whenever the tiling controls change.
+    generator: Box<dyn TerrainGenerator>,
     first_frame: bool,
+    /// A copy of what was last sent to the renderer, so we can pick tiles from it.
+    world: World,
+    /// The tiles of a map that's been loaded, waiting for the world to be cleared.
+    loaded: Option<Vec<Vec<Tile>>>,
+    /// What was last sent to Renderer::set_sprites.
+    sprites: Vec<Sprite>,
+    /// How far the arrow keys have moved the camera from the middle of the map.
+    pan: hexa::na::Vector2<f32>,
+    /// Where the cursor is, in logical pixels.
+    cursor: Option<hexa::na::Point2<f32>>,
+    /// The tile under the cursor, if there is one.
+    pub hovered: Option<pick::Hit>,
+    /// The column last clicked on.
+    pub selected: Option<Offset>,
+    /// Whether the tile under the cursor was clicked on while editing,
+    /// so the tool should be used on it next update.
+    edit_requested: bool,
+    /// The edits that can be undone and redone.
+    history: History,
+    /// Undos and redos asked for since the last update.
+    steps: Vec<Step>,
+    /// The camera and tiling settings last update, to tell when a slider has moved.
+    settings: Option<(controls::camera::Settings, controls::tiling::Data)>,
+    /// What was last sent to Renderer::set_highlights.
+    highlights: Vec<(Offset, Highlight)>,
+    /// The last RenderStats we logged.
+    stats: RenderStats,
+    /// When the time of day was last moved along.
+    last_update: std::time::Instant,
+    /// The minute of the day the renderer was last given the lighting and sky for.
+    /// Only whole minutes count, since the hour moves every frame while time runs, and new
+    /// lighting makes the renderer cull the shadow pass again.
+    minute: Option<u32>,
 }
 impl HacksteadScene {
     pub fn new(r: &mut dyn Renderer) -> Self {
+        Self::with_controls(r, Controls::new())
+    }
+
+    /// Builds a scene from the given controls instead of the saved ones,
+    /// useful for rendering the same scene reproducibly.
+    pub fn with_controls(r: &mut dyn Renderer, controls: Controls) -> Self {
         let screen = r.screen_size();
         let camera = Camera::new(screen.width as f32, screen.height as f32);
+        let textures = Textures::new(r);
         let (render, debug) = r.iced_mut();
-        let gui = program::State::new(Controls::new(), screen, render, debug);
+        let gui = program::State::new(controls, screen, render, debug);
 
         Self {
             gui,
             camera: camera.clone(),
+            textures,
+            biomes: Biomes::load(r),
+            // replaced on the first update, since the tiling controls start out dirty
+            generator: Box::new(terrain::Flat::new(0.0)),
             first_frame: true,
+            world: World::default(),
+            loaded: None,
+            sprites: vec![Sprite {
+                image: textures.stump,
+                position: hexa::na::Vector2::new(8.0, 7.5),
+                scale: hexa::na::Vector2::repeat(1.0),
+            }],
+            pan: hexa::na::Vector2::zeros(),
+            cursor: None,
+            hovered: None,
+            selected: None,
+            edit_requested: false,
+            history: History::default(),
+            steps: Vec::new(),
+            settings: None,
+            highlights: Vec::new(),
+            stats: RenderStats::default(),
+            last_update: std::time::Instant::now(),
+            minute: None,
+        }
+    }
+
+    /// This hackstead as a map, with the tiles of every chunk that's loaded
+    /// and every column that's been edited.
+    pub fn to_map(&self, r: &dyn Renderer) -> Map {
+        /// Gives each texture an index the first time it's seen.
+        fn intern(
+            ids: &mut HashMap<TextureId, u32>,
+            names: &mut Vec<String>,
+            id: TextureId,
+            name: impl FnOnce(TextureId) -> Option<String>,
+        ) -> u32 {
+            *ids.entry(id).or_insert_with(|| {
+                names.push(name(id).unwrap_or_else(|| {
+                    log::error!("texture {:?} has no name, it won't load", id);
+                    String::new()
+                }));
+                names.len() as u32 - 1
+            })
+        }
+
+        let (mut tile_ids, mut tile_textures) = (HashMap::new(), Vec::new());
+        let tile_name = |id| r.tile_texture_name(id).map(str::to_string);
+        let columns = self
+            .world
+            .columns_and_edits()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(|tile| MapTile {
+                        col: tile.position.col,
+                        row: tile.position.row,
+                        elevation: tile.elevation,
+                        butt_size: tile.butt_size,
+                        hat: intern(&mut tile_ids, &mut tile_textures, tile.hat, tile_name),
+                        butt: intern(&mut tile_ids, &mut tile_textures, tile.butt, tile_name),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (mut sprite_ids, mut sprite_textures) = (HashMap::new(), Vec::new());
+        let sprite_name = |id| r.sprite_texture_name(id).map(str::to_string);
+        let sprites = self
+            .sprites
+            .iter()
+            .map(|sprite| MapSprite {
+                image: intern(
+                    &mut sprite_ids,
+                    &mut sprite_textures,
+                    sprite.image,
+                    sprite_name,
+                ),
+                position: [sprite.position.x, sprite.position.y],
+                scale: [sprite.scale.x, sprite.scale.y],
+            })
+            .collect();
+
+        let controls = self.gui.program();
+        Map {
+            version: map::VERSION,
+            camera: MapCamera::new(&controls.camera_tab, [self.pan.x, self.pan.y]),
+            tiling: controls.tiling_tab.data,
+            tile_textures,
+            sprite_textures,
+            columns,
+            sprites,
+        }
+    }
+
+    /// Replaces this hackstead with a map. The camera and tiling controls change right away,
+    /// and the tiles are swapped in on the next update.
+    /// Textures the renderer doesn't have are logged, and the first texture is used instead.
+    pub fn load_map(&mut self, map: Map, r: &mut dyn Renderer) {
+        let find = |texture: Option<TextureId>, name: &str| {
+            texture.unwrap_or_else(|| {
+                log::error!("no texture named {} was loaded", name);
+                TextureId::default()
+            })
+        };
+        let tiles = map
+            .tile_textures
+            .iter()
+            .map(|name| find(r.tile_texture(name), name))
+            .collect::<Vec<_>>();
+        let sprites = map
+            .sprite_textures
+            .iter()
+            .map(|name| find(r.sprite_texture(name), name))
+            .collect::<Vec<_>>();
+
+        self.world.forget_edits();
+        self.loaded = Some(
+            map.columns
+                .iter()
+                .map(|column| {
+                    column
+                        .iter()
+                        .map(|tile| Tile {
+                            position: Offset::new(tile.col, tile.row),
+                            elevation: tile.elevation,
+                            butt_size: tile.butt_size,
+                            hat: tiles.get(tile.hat as usize).copied().unwrap_or_default(),
+                            butt: tiles.get(tile.butt as usize).copied().unwrap_or_default(),
+                            highlight: None,
+                        })
+                        .collect()
+                })
+                .collect(),
+        );
+
+        self.sprites = map
+            .sprites
+            .iter()
+            .map(|sprite| Sprite {
+                image: sprites
+                    .get(sprite.image as usize)
+                    .copied()
+                    .unwrap_or_default(),
+                position: sprite.position.into(),
+                scale: sprite.scale.into(),
+            })
+            .collect();
+        r.set_sprites(self.sprites.clone());
+
+        self.pan = map.camera.pan.into();
+        self.selected = None;
+        self.gui
+            .queue_message(controls::Message::MapLoaded(map.camera, map.tiling));
+    }
+
+    /// Writes this hackstead to a map file, as JSON if the path ends in `.json`.
+    pub fn save_map(&self, path: &Path, r: &dyn Renderer) -> Result<(), MapError> {
+        self.to_map(r).write(path, map::Format::from_path(path))
+    }
+
+    /// Reads a map file in either format, and loads it like `load_map`.
+    pub fn open_map(&mut self, path: &Path, r: &mut dyn Renderer) -> Result<(), MapError> {
+        let map = Map::read(path)?;
+        self.load_map(map, r);
+        Ok(())
+    }
+
+    /// Replaces columns of tiles in both the world and the renderer, returning a command that
+    /// records the change. Columns in chunks that aren't loaded are left out.
+    fn set_columns(&mut self, columns: Vec<(Offset, Vec<Tile>)>, r: &mut dyn Renderer) -> Command {
+        let before = self.world.set_columns(&columns);
+        let replaced = before.iter().map(|(p, _)| *p).collect::<HashSet<_>>();
+        let after = columns
+            .into_iter()
+            .filter(|(p, _)| replaced.contains(p))
+            .collect::<Vec<_>>();
+        r.update_tiles(after.clone());
+        Command::Tiles { before, after }
+    }
+
+    /// Undoes or redoes an edit by making things how they were after this command.
+    fn apply(&mut self, command: Command, r: &mut dyn Renderer) {
+        match command {
+            Command::Tiles { after, .. } => {
+                self.set_columns(after, r);
+            }
+            Command::Sprites { after, .. } => {
+                r.set_sprites(after.clone());
+                self.sprites = after;
+            }
+            // the settings are what the controls are about to be,
+            // so the change doesn't look like a new edit once they are
+            Command::Camera { after, .. } => {
+                if let Some((camera, _)) = &mut self.settings {
+                    *camera = after;
+                }
+                self.gui.queue_message(controls::Message::Camera(
+                    controls::camera::Message::Restored(after),
+                ));
+            }
+            Command::Tiling { after, .. } => {
+                if let Some((_, tiling)) = &mut self.settings {
+                    *tiling = after;
+                }
+                self.gui.queue_message(controls::Message::Tiling(
+                    controls::tiling::Message::Restored(after),
+                ));
+            }
         }
     }
 }
@@ -39,6 +331,57 @@ impl Scene for HacksteadScene {
                 self.camera
                     .resize(new_size.width as f32, new_size.height as f32);
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                let p = position.to_logical::<f32>(scale_factor);
+                self.cursor = Some(hexa::na::Point2::new(p.x, p.y));
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+            }
+            // the cursor is only idle when it isn't over a widget, so clicks on the controls
+            // don't edit or select the tiles behind them
+            WindowEvent::MouseInput {
+                state: winit::event::ElementState::Pressed,
+                button: winit::event::MouseButton::Left,
+                ..
+            } if self.gui.primitive().1 == mouse::Interaction::Idle => {
+                if self.gui.program().edit_tab.editing {
+                    self.edit_requested = true;
+                } else {
+                    self.selected = self.hovered.map(|hit| hit.position);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if !self.gui.program().typing() => {
+                use winit::event::VirtualKeyCode::*;
+
+                if *key == Z && modifiers.ctrl() {
+                    self.steps.push(if modifiers.shift() {
+                        Step::Redo
+                    } else {
+                        Step::Undo
+                    });
+                }
+
+                let (sin, cos) = self.gui.program().camera_tab.angle.sin_cos();
+                let forward = -hexa::na::Vector2::new(cos, sin);
+                let right = hexa::na::Vector2::new(-sin, cos);
+                self.pan += PAN_STEP
+                    * match key {
+                        Up | W => forward,
+                        Down | S => -forward,
+                        Right | D => right,
+                        Left | A => -right,
+                        _ => hexa::na::Vector2::zeros(),
+                    };
+            }
             _ => {}
         }
 
@@ -58,76 +401,185 @@ impl Scene for HacksteadScene {
         let _ = self.gui.update(None, screen, render, debug);
 
         if self.first_frame {
-            renderer.set_sprites(vec![Sprite {
-                image: 0,
-                position: hexa::na::Vector2::new(8.0, 7.5),
-                scale: hexa::na::Vector2::repeat(1.0),
-            }]);
+            renderer.set_sprites(self.sprites.clone());
+
+            let palette = (0u32..)
+                .map(|i| renderer.tile_texture_name(TextureId(i)).map(str::to_string))
+                .take_while(Option::is_some)
+                .flatten()
+                .collect();
+            self.gui.queue_message(controls::Message::Edit(
+                controls::edit::Message::PaletteLoaded(palette),
+            ));
 
             self.first_frame = false;
         }
 
+        let now = std::time::Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
+        self.gui
+            .queue_message(controls::Message::Time(controls::time::Message::Elapsed(
+                elapsed,
+            )));
+
         let Controls {
             tiling_tab,
             camera_tab,
+            time_tab,
+            edit_tab,
+            save_tab,
             ..
         } = self.gui.program();
 
+        let minute = (time_tab.hour * 60.0) as u32;
+        if self.minute != Some(minute) {
+            let (lighting, sky) = daylight::at(minute as f32 / 60.0);
+            renderer.set_lighting(&lighting);
+            renderer.set_sky(&sky);
+            self.minute = Some(minute);
+        }
+
+        let middle = tiling_tab.size as f32 / 2.0 + 1.0;
         renderer.set_camera({
             self.camera.eye.z = camera_tab.height;
             self.camera.set_angle(camera_tab.angle, camera_tab.distance);
             self.camera.target =
-                hexa::na::Point3::new(1.0, 1.0, 0.0) * (tiling_tab.size as f32 / 2.0 + 1.0);
+                hexa::na::Point3::new(middle + self.pan.x, middle + self.pan.y, 0.0);
             self.camera.fovy = camera_tab.fov;
             &self.camera
         });
 
+        let data = tiling_tab.data;
+        let export = tiling_tab.export;
+        let (editing, tool, brush) = (edit_tab.editing, edit_tab.tool, edit_tab.brush);
+        let texture = TextureId(edit_tab.texture as u32);
+        let map_request = save_tab.map.clone();
+
+        let settings = (camera_tab.settings(), data);
+        if let Some((camera, tiling)) = self.settings.replace(settings) {
+            if camera != settings.0 {
+                self.history.record(Command::Camera {
+                    before: camera,
+                    after: settings.0,
+                });
+            }
+            if tiling != settings.1 {
+                self.history.record(Command::Tiling {
+                    before: tiling,
+                    after: settings.1,
+                });
+            }
+        }
+
         if tiling_tab.dirty {
-            use noise::{NoiseFn, Seedable};
-
-            let perlin = noise::Perlin::new().set_seed(tiling_tab.seed);
-            let g = tiling_tab.size as f64;
-            let e = tiling_tab.elevation as f32;
-
-            renderer.set_tiles(
-                (0..tiling_tab.size)
-                    .flat_map(|x| {
-                        (0..tiling_tab.size).filter_map(move |y| {
-                            use hexa::na::Vector2 as Vec2;
-                            let position = Vec2::new(x, y);
-                            let noise = perlin.get([x as f64 / g, y as f64 / g]) as f32 * e;
-
-                            let mut tiles = vec![Tile {
-                                position,
-                                elevation: 0.0,
-                                hat: 2,
-                                butt: 3,
-                                butt_size: noise + 0.3,
-                            }];
-
-                            if noise > e / 2.0 {
-                                tiles.push(Tile {
-                                    position,
-                                    elevation: 0.0,
-                                    hat: 0,
-                                    butt: 1,
-                                    butt_size: noise * (noise / 1.5) * 0.4,
-                                });
-                            }
+            self.world.clear(renderer);
+            renderer.set_sea_level(Some(data.sea_level));
+            self.generator = data.generator.build(&data);
+            if let Some(columns) = self.loaded.take() {
+                self.world.fix(columns);
+            }
+
+            self.gui.queue_message(controls::Message::Tiling(
+                controls::tiling::Message::Retiled,
+            ));
+        }
+
+        if let Some(request) = map_request {
+            let status = match request {
+                controls::save::MapRequest::Save(path) => self
+                    .save_map(&path, renderer)
+                    .map_err(|e| format!("couldn't save {}: {}", path.display(), e)),
+                // slots saved before there were maps have only the controls
+                controls::save::MapRequest::Load(path) => match self.open_map(&path, renderer) {
+                    Err(MapError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                    other => other.map_err(|e| format!("couldn't load {}: {}", path.display(), e)),
+                },
+            };
+            self.gui
+                .queue_message(controls::Message::Save(controls::save::Message::MapDone(
+                    status,
+                )));
+        }
 
-                            if noise > 0.0 {
-                                Some(tiles)
+        let generator = &self.generator;
+        let biomes = &self.biomes;
+        let center = coord::Axial::from_world(self.camera.target.coords.xy()).to_offset();
+        self.world.stream(center, renderer, |chunk| {
+            generator.generate_chunk(chunk, &data, biomes)
+        });
+
+        if export {
+            let path = terrain::heightmap::heightmap_path();
+            match terrain::heightmap::export(self.world.columns(), data.elevation).save(&path) {
+                Ok(()) => log::info!("exported heightmap to {}", path.display()),
+                Err(e) => log::error!("couldn't export heightmap to {}: {}", path.display(), e),
+            }
+
+            self.gui.queue_message(controls::Message::Tiling(
+                controls::tiling::Message::HeightmapExported,
+            ));
+        }
+
+        if std::mem::replace(&mut self.edit_requested, false) {
+            if let Some(hit) = self.hovered {
+                if tool == editor::Tool::PlaceSprite {
+                    let before = self.sprites.clone();
+                    editor::toggle_sprite(&mut self.sprites, hit.position, self.textures.stump);
+                    renderer.set_sprites(self.sprites.clone());
+                    self.history.record(Command::Sprites {
+                        before,
+                        after: self.sprites.clone(),
+                    });
+                } else {
+                    let changes = editor::brush(hit.position, brush)
+                        .filter_map(|p| {
+                            let mut column = self.world.column(p)?.to_vec();
+                            if tool.apply(&mut column, texture) {
+                                Some((p, column))
                             } else {
                                 None
                             }
                         })
-                    })
-                    .collect(),
-            );
+                        .collect::<Vec<_>>();
+                    // so undo doesn't have to step through clicks that did nothing
+                    if !changes.is_empty() {
+                        let command = self.set_columns(changes, renderer);
+                        self.history.record(command);
+                    }
+                }
+            }
+        }
 
-            self.gui.queue_message(controls::Message::Tiling(
-                controls::tiling::Message::Retiled,
-            ));
+        for step in std::mem::take(&mut self.steps) {
+            if let Some(command) = self.history.step(step) {
+                self.apply(command, renderer);
+            }
+        }
+
+        self.hovered = self.cursor.and_then(|cursor| {
+            let ray = self.camera.ray(screen, cursor);
+            pick::pick(&ray, self.world.columns())
+        });
+
+        // while editing, the whole area the brush would cover is highlighted
+        let brush = if editing { brush } else { 0 };
+        let highlights = self
+            .hovered
+            .into_iter()
+            .flat_map(|hit| editor::brush(hit.position, brush))
+            .map(|p| (p, Highlight::HOVERED))
+            .chain(self.selected.map(|p| (p, Highlight::SELECTED)))
+            .collect::<Vec<_>>();
+        if highlights != self.highlights {
+            renderer.set_highlights(highlights.clone());
+            self.highlights = highlights;
+        }
+
+        let stats = renderer.render_stats();
+        if stats != self.stats {
+            log::debug!("{:?}", stats);
+            self.stats = stats;
         }
     }
 }
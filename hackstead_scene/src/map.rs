@@ -0,0 +1,592 @@
+//! Hacksteads saved to disk, with every tile and sprite written out explicitly,
+//! so a map loads the same even after the terrain generators change.
+//!
+//! Maps are written either as JSON or in a compact binary format, and reading one tells
+//! them apart by the binary format's magic bytes. Textures are stored by name, since the ids
+//! the renderer hands out change whenever images are added to the asset directory.
+//!
+//! Saves from before there were maps only have the camera and tiling controls in them;
+//! those are read as version 0, and migrated to maps with no tiles, so the whole map is
+//! generated from the tiling controls when it's loaded.
+use crate::controls::{camera::CameraControls, tiling};
+use crate::terrain::Generator;
+use byteorder::{LittleEndian as LE, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// The version maps are written with.
+pub const VERSION: u32 = 1;
+/// The first bytes of every map in the binary format.
+const MAGIC: &[u8; 4] = b"HXMP";
+/// How many bytes each tile and sprite takes up in the binary format.
+const TILE_SIZE: usize = 24;
+const SPRITE_SIZE: usize = 20;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// Written by a newer version of this crate, which this one can't read.
+    UnsupportedVersion(u32),
+    /// Something in the map that can't be right, like a tile using a texture that isn't listed.
+    Invalid(String),
+}
+impl std::fmt::Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "{}", e),
+            MapError::Json(e) => write!(f, "bad JSON: {}", e),
+            MapError::UnsupportedVersion(v) => write!(
+                f,
+                "map is version {}, but only versions up to {} can be read",
+                v, VERSION
+            ),
+            MapError::Invalid(why) => write!(f, "invalid map: {}", why),
+        }
+    }
+}
+impl std::error::Error for MapError {}
+impl From<std::io::Error> for MapError {
+    fn from(e: std::io::Error) -> Self {
+        MapError::Io(e)
+    }
+}
+impl From<serde_json::Error> for MapError {
+    fn from(e: serde_json::Error) -> Self {
+        MapError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Binary,
+}
+impl Format {
+    /// JSON for paths ending in `.json`, the binary format for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(e) if e == "json" => Format::Json,
+            _ => Format::Binary,
+        }
+    }
+}
+
+/// Where the camera was, and how it was set up.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MapCamera {
+    pub fov: f32,
+    pub height: f32,
+    pub angle: f32,
+    pub distance: f32,
+    /// How far the arrow keys had moved the camera from the middle of the map.
+    pub pan: [f32; 2],
+}
+impl MapCamera {
+    pub fn new(controls: &CameraControls, pan: [f32; 2]) -> Self {
+        Self {
+            fov: controls.fov,
+            height: controls.height,
+            angle: controls.angle,
+            distance: controls.distance,
+            pan,
+        }
+    }
+}
+
+/// A hexa::Tile, with its textures as indices into `Map::tile_textures`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MapTile {
+    pub col: i32,
+    pub row: i32,
+    pub elevation: f32,
+    pub butt_size: f32,
+    pub hat: u32,
+    pub butt: u32,
+}
+
+/// A hexa::Sprite, with its image as an index into `Map::sprite_textures`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct MapSprite {
+    pub image: u32,
+    pub position: [f32; 2],
+    pub scale: [f32; 2],
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Map {
+    pub version: u32,
+    pub camera: MapCamera,
+    /// What the map was generated with; anywhere without tiles in `columns` is still
+    /// generated from this when the map is loaded.
+    pub tiling: tiling::Data,
+    /// The names of the tile textures, see Renderer::tile_texture.
+    pub tile_textures: Vec<String>,
+    /// The names of the sprite textures, see Renderer::sprite_texture.
+    pub sprite_textures: Vec<String>,
+    /// Stacks of tiles, from the bottom up.
+    pub columns: Vec<Vec<MapTile>>,
+    pub sprites: Vec<MapSprite>,
+}
+
+/// What save.json held before there were maps.
+#[derive(serde::Deserialize)]
+struct SaveV0 {
+    camera_tab: CameraControls,
+    tiling_tab: tiling::TilingControls,
+}
+
+impl Map {
+    /// Reads a map in either format, migrating it from older versions if need be.
+    pub fn read(path: &Path) -> Result<Self, MapError> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(MAGIC) {
+            Self::from_binary(&bytes)
+        } else {
+            Self::from_json(
+                std::str::from_utf8(&bytes)
+                    .map_err(|e| MapError::Invalid(format!("neither binary nor JSON: {}", e)))?,
+            )
+        }
+    }
+
+    /// Writes to a temporary file that's then renamed over `path`,
+    /// so a crash halfway through never leaves a map half written.
+    pub fn write(&self, path: &Path, format: Format) -> Result<(), MapError> {
+        let bytes = match format {
+            Format::Json => self.to_json()?.into_bytes(),
+            Format::Binary => self.to_binary()?,
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String, MapError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MapError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let version = match value.get("version") {
+            None => 0,
+            Some(v) => v
+                .as_u64()
+                .ok_or_else(|| MapError::Invalid(format!("version {} isn't a number", v)))?
+                as u32,
+        };
+
+        let map = match version {
+            0 => Self::migrate_v0(serde_json::from_value(value)?),
+            VERSION => serde_json::from_value(value)?,
+            other => return Err(MapError::UnsupportedVersion(other)),
+        };
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Saves from before maps have no tiles, and always had a stump in the same place.
+    fn migrate_v0(save: SaveV0) -> Self {
+        Self {
+            version: VERSION,
+            camera: MapCamera::new(&save.camera_tab, [0.0, 0.0]),
+            tiling: save.tiling_tab.data,
+            tile_textures: Vec::new(),
+            sprite_textures: vec!["stump".to_string()],
+            columns: Vec::new(),
+            sprites: vec![MapSprite {
+                image: 0,
+                position: [8.0, 7.5],
+                scale: [1.0, 1.0],
+            }],
+        }
+    }
+
+    /// Makes sure every texture index points at a texture, and no column is empty.
+    fn validate(&self) -> Result<(), MapError> {
+        let tile_textures = self.tile_textures.len() as u32;
+        for tile in self.columns.iter().flatten() {
+            if tile.hat >= tile_textures || tile.butt >= tile_textures {
+                return Err(MapError::Invalid(format!(
+                    "the tile at {}, {} uses a texture that isn't listed",
+                    tile.col, tile.row
+                )));
+            }
+        }
+        if self.columns.iter().any(|column| column.is_empty()) {
+            return Err(MapError::Invalid("there's an empty column".to_string()));
+        }
+        if self
+            .sprites
+            .iter()
+            .any(|sprite| sprite.image >= self.sprite_textures.len() as u32)
+        {
+            return Err(MapError::Invalid(
+                "a sprite uses a texture that isn't listed".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn to_binary(&self) -> Result<Vec<u8>, MapError> {
+        let mut w = Vec::new();
+        w.write_all(MAGIC)?;
+        w.write_u32::<LE>(self.version)?;
+
+        let MapCamera {
+            fov,
+            height,
+            angle,
+            distance,
+            pan,
+        } = self.camera;
+        for &f in &[fov, height, angle, distance, pan[0], pan[1]] {
+            w.write_f32::<LE>(f)?;
+        }
+
+        let t = &self.tiling;
+        w.write_f32::<LE>(t.elevation)?;
+        w.write_u32::<LE>(t.size)?;
+        w.write_u32::<LE>(t.seed)?;
+        w.write_u8(t.infinite as u8)?;
+        w.write_f32::<LE>(t.sea_level)?;
+        w.write_u8(
+            Generator::ALL
+                .iter()
+                .position(|&g| g == t.generator)
+                .unwrap_or(0) as u8,
+        )?;
+        w.write_u32::<LE>(t.octaves)?;
+
+        for names in &[&self.tile_textures, &self.sprite_textures] {
+            w.write_u32::<LE>(names.len() as u32)?;
+            for name in names.iter() {
+                w.write_u32::<LE>(name.len() as u32)?;
+                w.write_all(name.as_bytes())?;
+            }
+        }
+
+        w.write_u32::<LE>(self.columns.len() as u32)?;
+        for column in &self.columns {
+            w.write_u32::<LE>(column.len() as u32)?;
+            for tile in column {
+                w.write_i32::<LE>(tile.col)?;
+                w.write_i32::<LE>(tile.row)?;
+                w.write_f32::<LE>(tile.elevation)?;
+                w.write_f32::<LE>(tile.butt_size)?;
+                w.write_u32::<LE>(tile.hat)?;
+                w.write_u32::<LE>(tile.butt)?;
+            }
+        }
+
+        w.write_u32::<LE>(self.sprites.len() as u32)?;
+        for sprite in &self.sprites {
+            w.write_u32::<LE>(sprite.image)?;
+            for &f in &[
+                sprite.position[0],
+                sprite.position[1],
+                sprite.scale[0],
+                sprite.scale[1],
+            ] {
+                w.write_f32::<LE>(f)?;
+            }
+        }
+
+        Ok(w)
+    }
+
+    pub fn from_binary(mut r: &[u8]) -> Result<Self, MapError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(MapError::Invalid("not a binary map".to_string()));
+        }
+        match r.read_u32::<LE>()? {
+            VERSION => {}
+            other => return Err(MapError::UnsupportedVersion(other)),
+        }
+
+        let mut floats = |n: usize| -> Result<Vec<f32>, MapError> {
+            (0..n).map(|_| Ok(r.read_f32::<LE>()?)).collect()
+        };
+        let c = floats(6)?;
+        let camera = MapCamera {
+            fov: c[0],
+            height: c[1],
+            angle: c[2],
+            distance: c[3],
+            pan: [c[4], c[5]],
+        };
+
+        let tiling = tiling::Data {
+            elevation: r.read_f32::<LE>()?,
+            size: r.read_u32::<LE>()?,
+            seed: r.read_u32::<LE>()?,
+            infinite: r.read_u8()? != 0,
+            sea_level: r.read_f32::<LE>()?,
+            generator: {
+                let i = r.read_u8()? as usize;
+                *Generator::ALL
+                    .get(i)
+                    .ok_or_else(|| MapError::Invalid(format!("there's no generator {}", i)))?
+            },
+            octaves: r.read_u32::<LE>()?,
+        };
+
+        let mut names = || -> Result<Vec<String>, MapError> {
+            (0..read_len(&mut r, 4)?)
+                .map(|_| {
+                    let mut name = vec![0; read_len(&mut r, 1)?];
+                    r.read_exact(&mut name)?;
+                    String::from_utf8(name).map_err(|e| MapError::Invalid(e.to_string()))
+                })
+                .collect()
+        };
+        let tile_textures = names()?;
+        let sprite_textures = names()?;
+
+        let columns = (0..read_len(&mut r, 4)?)
+            .map(|_| {
+                (0..read_len(&mut r, TILE_SIZE)?)
+                    .map(|_| {
+                        Ok(MapTile {
+                            col: r.read_i32::<LE>()?,
+                            row: r.read_i32::<LE>()?,
+                            elevation: r.read_f32::<LE>()?,
+                            butt_size: r.read_f32::<LE>()?,
+                            hat: r.read_u32::<LE>()?,
+                            butt: r.read_u32::<LE>()?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, MapError>>()
+            })
+            .collect::<Result<Vec<_>, MapError>>()?;
+
+        let sprites = (0..read_len(&mut r, SPRITE_SIZE)?)
+            .map(|_| {
+                Ok(MapSprite {
+                    image: r.read_u32::<LE>()?,
+                    position: [r.read_f32::<LE>()?, r.read_f32::<LE>()?],
+                    scale: [r.read_f32::<LE>()?, r.read_f32::<LE>()?],
+                })
+            })
+            .collect::<Result<Vec<_>, MapError>>()?;
+
+        let map = Self {
+            version: VERSION,
+            camera,
+            tiling,
+            tile_textures,
+            sprite_textures,
+            columns,
+            sprites,
+        };
+        map.validate()?;
+        Ok(map)
+    }
+}
+
+/// Reads how many of something come next in a binary map, making sure there's room left for
+/// that many of `size` bytes each, so a corrupt count can't ask for a huge allocation.
+fn read_len(r: &mut &[u8], size: usize) -> Result<usize, MapError> {
+    let n = r.read_u32::<LE>()? as usize;
+    if n.saturating_mul(size) > r.len() {
+        return Err(MapError::Invalid(format!(
+            "{} things of {} bytes each can't fit in the {} bytes left",
+            n,
+            size,
+            r.len()
+        )));
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map() -> Map {
+        Map {
+            version: VERSION,
+            camera: MapCamera {
+                fov: 1.5,
+                height: 3.0,
+                angle: 0.5,
+                distance: 6.0,
+                pan: [1.5, -3.0],
+            },
+            tiling: tiling::Data {
+                generator: Generator::Ridged,
+                infinite: true,
+                ..Default::default()
+            },
+            tile_textures: vec!["snow_hat".to_string(), "snow_butt".to_string()],
+            sprite_textures: vec!["stump".to_string()],
+            columns: vec![
+                vec![MapTile {
+                    col: 0,
+                    row: 0,
+                    elevation: 1.0,
+                    butt_size: 1.0,
+                    hat: 0,
+                    butt: 1,
+                }],
+                vec![
+                    MapTile {
+                        col: -1,
+                        row: 2,
+                        elevation: 0.5,
+                        butt_size: 0.5,
+                        hat: 0,
+                        butt: 1,
+                    },
+                    MapTile {
+                        col: -1,
+                        row: 2,
+                        elevation: 0.75,
+                        butt_size: 0.25,
+                        hat: 1,
+                        butt: 0,
+                    },
+                ],
+            ],
+            sprites: vec![MapSprite {
+                image: 0,
+                position: [2.0, 3.5],
+                scale: [1.0, 2.0],
+            }],
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let map = map();
+        assert_eq!(Map::from_json(&map.to_json().unwrap()).unwrap(), map);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let map = map();
+        assert_eq!(Map::from_binary(&map.to_binary().unwrap()).unwrap(), map);
+    }
+
+    #[test]
+    fn read_tells_the_formats_apart() {
+        let map = map();
+        let dir = std::env::temp_dir().join(format!("hexa-map-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in &["map.json", "map.map"] {
+            let path = dir.join(name);
+            map.write(&path, Format::from_path(&path)).unwrap();
+            assert_eq!(Map::read(&path).unwrap(), map);
+        }
+        assert!(std::fs::read(dir.join("map.map"))
+            .unwrap()
+            .starts_with(MAGIC));
+        assert!(!dir.join("map.map.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn v0_saves_are_migrated() {
+        let json = r#"{
+            "camera_tab": { "fov": 1.0, "height": 2.0, "angle": 3.0, "distance": 4.0 },
+            "tiling_tab": { "data": { "elevation": 0.5, "size": 7, "seed": 3 } }
+        }"#;
+        let map = Map::from_json(json).unwrap();
+
+        assert_eq!(map.version, VERSION);
+        assert_eq!(
+            map.camera,
+            MapCamera {
+                fov: 1.0,
+                height: 2.0,
+                angle: 3.0,
+                distance: 4.0,
+                pan: [0.0, 0.0],
+            }
+        );
+        assert_eq!(map.tiling.size, 7);
+        assert_eq!(map.tiling.seed, 3);
+        assert!(map.columns.is_empty());
+        assert_eq!(map.sprite_textures, vec!["stump".to_string()]);
+        assert_eq!(map.sprites.len(), 1);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut json: serde_json::Value = serde_json::from_str(&map().to_json().unwrap()).unwrap();
+        json["version"] = (VERSION + 1).into();
+        match Map::from_json(&json.to_string()) {
+            Err(MapError::UnsupportedVersion(v)) => assert_eq!(v, VERSION + 1),
+            other => panic!("expected an unsupported version, got {:?}", other),
+        }
+
+        let mut binary = map().to_binary().unwrap();
+        binary[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Map::from_binary(&binary),
+            Err(MapError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_maps_are_refused() {
+        let mut unlisted_tile_texture = map();
+        unlisted_tile_texture.columns[0][0].butt = 2;
+        let mut empty_column = map();
+        empty_column.columns.push(Vec::new());
+        let mut unlisted_sprite_texture = map();
+        unlisted_sprite_texture.sprites[0].image = 1;
+
+        for map in &[unlisted_tile_texture, empty_column, unlisted_sprite_texture] {
+            assert!(matches!(map.validate(), Err(MapError::Invalid(_))));
+            assert!(matches!(
+                Map::from_json(&map.to_json().unwrap()),
+                Err(MapError::Invalid(_))
+            ));
+            assert!(matches!(
+                Map::from_binary(&map.to_binary().unwrap()),
+                Err(MapError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn huge_counts_are_refused_before_allocating() {
+        let map = Map {
+            columns: Vec::new(),
+            sprites: Vec::new(),
+            ..map()
+        };
+        let mut binary = map.to_binary().unwrap();
+        // the sprite count is the last thing written
+        let at = binary.len() - 4;
+        binary[at..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Map::from_binary(&binary),
+            Err(MapError::Invalid(_))
+        ));
+
+        // the length of the first tile texture's name comes right after how many there are,
+        // which comes after the magic bytes, version, camera and tiling settings
+        let mut binary = map.to_binary().unwrap();
+        let names = 4 + 4 + 6 * 4 + (4 + 4 + 4 + 1 + 4 + 1 + 4) + 4;
+        assert_eq!(&binary[names - 4..names], &2u32.to_le_bytes());
+        binary[names..names + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Map::from_binary(&binary),
+            Err(MapError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_maps_are_refused() {
+        let binary = map().to_binary().unwrap();
+        for len in 0..binary.len() {
+            assert!(Map::from_binary(&binary[..len]).is_err(), "{} bytes", len);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Which tiles the ground is made of where, looked up in a table of biomes.
+//!
+//! The table is a JSON array of biomes, read from `biomes.json`. Each biome says which
+//! columns it covers with ranges of elevation, moisture and temperature, and which layers
+//! of tiles those columns are stacked out of. A column gets the first biome that covers it.
+//!
+//! Elevation is how far the ground is above the sea, as a fraction of the Elevation slider,
+//! so 0.0 is the shore and 1.0 is the highest the ground usually gets.
+//! Moisture and temperature come from their own noise, and go from -1.0 to 1.0.
+use hexa::{coord::Offset, Renderer, TextureId};
+use noise::{NoiseFn, Seedable};
+use std::path::PathBuf;
+
+/// The table in the `biomes.json` this crate was built with,
+/// for when the one on disk can't be read.
+const BUILT_IN: &str = include_str!("../../biomes.json");
+
+/// Where the biome table is read from: `$HEXA_BIOMES` if it's set, otherwise the closest
+/// `biomes.json` to the executable (this crate's, in a build from the workspace),
+/// otherwise `biomes.json` in the working directory.
+pub fn biomes_path() -> PathBuf {
+    std::env::var_os("HEXA_BIOMES")
+        .map(PathBuf::from)
+        .or_else(|| hexa::paths::beside_executable(&["biomes.json", "hackstead_scene/biomes.json"]))
+        .unwrap_or_else(|| PathBuf::from("biomes.json"))
+}
+
+/// Values from `min` up to but not including `max`. Either end can be left out.
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default)]
+pub struct Range {
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+impl Range {
+    pub fn contains(self, x: f32) -> bool {
+        self.min.map_or(true, |min| x >= min) && self.max.map_or(true, |max| x < max)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct LayerEntry {
+    hat: String,
+    butt: String,
+    #[serde(default)]
+    thickness: f32,
+}
+
+#[derive(serde::Deserialize)]
+struct BiomeEntry {
+    name: String,
+    #[serde(default)]
+    elevation: Range,
+    #[serde(default)]
+    moisture: Range,
+    #[serde(default)]
+    temperature: Range,
+    layers: Vec<LayerEntry>,
+}
+
+/// One tile in each column of a biome.
+#[derive(Debug, Clone, Copy)]
+pub struct Layer {
+    pub hat: TextureId,
+    pub butt: TextureId,
+    /// How tall the tile is, times the square of how high the ground is,
+    /// so layers pile up thicker on higher ground.
+    /// The first layer of a biome ignores this, and always reaches all the way down.
+    pub thickness: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Biome {
+    pub name: String,
+    pub elevation: Range,
+    pub moisture: Range,
+    pub temperature: Range,
+    /// From the bottom of the stack up. Never empty.
+    pub layers: Vec<Layer>,
+}
+impl Biome {
+    pub fn covers(&self, elevation: f32, moisture: f32, temperature: f32) -> bool {
+        self.elevation.contains(elevation)
+            && self.moisture.contains(moisture)
+            && self.temperature.contains(temperature)
+    }
+}
+
+/// Every biome, in the order they're tried in.
+#[derive(Debug, Clone)]
+pub struct Biomes {
+    biomes: Vec<Biome>,
+}
+impl Biomes {
+    /// Reads the biome table from `biomes_path()`,
+    /// falling back to the one built into this crate if that doesn't work.
+    pub fn load(r: &dyn Renderer) -> Self {
+        let path = biomes_path();
+        std::fs::read_to_string(&path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))
+            .and_then(|json| Self::parse(&json, |name| r.tile_texture(name)))
+            .unwrap_or_else(|e| {
+                log::error!("using the built in biomes: {}", e);
+                Self::parse(BUILT_IN, |name| r.tile_texture(name))
+                    .expect("the built in biomes are broken")
+            })
+    }
+
+    /// Parses a biome table, looking up the tile textures it names with `texture`.
+    /// Textures that aren't loaded are logged, and the first texture is used instead.
+    pub fn parse(json: &str, texture: impl Fn(&str) -> Option<TextureId>) -> Result<Self, String> {
+        let entries: Vec<BiomeEntry> =
+            serde_json::from_str(json).map_err(|e| format!("bad biome table: {}", e))?;
+
+        let tile = |name: &str| {
+            texture(name).unwrap_or_else(|| {
+                log::error!("no texture named {} was loaded", name);
+                TextureId::default()
+            })
+        };
+        let biomes = entries
+            .into_iter()
+            .map(|entry| {
+                if entry.layers.is_empty() {
+                    return Err(format!("the {} biome has no layers", entry.name));
+                }
+
+                Ok(Biome {
+                    layers: entry
+                        .layers
+                        .iter()
+                        .map(|layer| Layer {
+                            hat: tile(&layer.hat),
+                            butt: tile(&layer.butt),
+                            thickness: layer.thickness,
+                        })
+                        .collect(),
+                    name: entry.name,
+                    elevation: entry.elevation,
+                    moisture: entry.moisture,
+                    temperature: entry.temperature,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if biomes.is_empty() {
+            return Err("there are no biomes in the table".to_string());
+        }
+        Ok(Self { biomes })
+    }
+
+    /// The first biome covering these conditions, or the last biome if none of them do.
+    pub fn at(&self, elevation: f32, moisture: f32, temperature: f32) -> &Biome {
+        self.biomes
+            .iter()
+            .find(|b| b.covers(elevation, moisture, temperature))
+            .unwrap_or_else(|| self.biomes.last().expect("there's always a biome"))
+    }
+}
+
+/// How wet and how warm each column is.
+pub struct Climate {
+    moisture: noise::Perlin,
+    temperature: noise::Perlin,
+    /// How many columns apart the wettest and driest places are.
+    scale: f64,
+}
+impl Climate {
+    pub fn new(seed: u32, scale: f64) -> Self {
+        Self {
+            moisture: noise::Perlin::new().set_seed(seed.wrapping_add(1)),
+            temperature: noise::Perlin::new().set_seed(seed.wrapping_add(2)),
+            scale,
+        }
+    }
+
+    /// The moisture and temperature here, each from -1.0 to 1.0.
+    pub fn at(&self, p: Offset) -> (f32, f32) {
+        let point = [p.col as f64 / self.scale, p.row as f64 / self.scale];
+        (
+            self.moisture.get(point) as f32,
+            self.temperature.get(point) as f32,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texture(name: &str) -> Option<TextureId> {
+        match name {
+            "a" => Some(TextureId(1)),
+            "b" => Some(TextureId(2)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn biomes_need_layers() {
+        let json = r#"[{ "name": "empty", "layers": [] }]"#;
+        assert_eq!(
+            Biomes::parse(json, texture).unwrap_err(),
+            "the empty biome has no layers"
+        );
+    }
+
+    #[test]
+    fn tables_need_biomes() {
+        assert_eq!(
+            Biomes::parse("[]", texture).unwrap_err(),
+            "there are no biomes in the table"
+        );
+    }
+
+    #[test]
+    fn missing_textures_are_the_first_texture() {
+        let json = r#"[{ "name": "x", "layers": [{ "hat": "a", "butt": "nope" }] }]"#;
+        let biomes = Biomes::parse(json, texture).unwrap();
+        let layer = biomes.at(0.0, 0.0, 0.0).layers[0];
+        assert_eq!(layer.hat, TextureId(1));
+        assert_eq!(layer.butt, TextureId::default());
+    }
+
+    #[test]
+    fn first_match_wins_and_last_is_the_fallback() {
+        let json = r#"[
+            { "name": "low", "elevation": { "max": 0.5 }, "layers": [{ "hat": "a", "butt": "a" }] },
+            { "name": "wet", "moisture": { "min": 0.0 }, "layers": [{ "hat": "b", "butt": "b" }] },
+            { "name": "warm", "temperature": { "min": 0.5 }, "layers": [{ "hat": "b", "butt": "a" }] }
+        ]"#;
+        let biomes = Biomes::parse(json, texture).unwrap();
+        let name = |e, m, t| biomes.at(e, m, t).name.as_str();
+
+        assert_eq!(name(0.0, 0.5, 0.0), "low");
+        assert_eq!(name(0.5, 0.5, 0.0), "wet");
+        assert_eq!(name(0.5, -0.5, 0.9), "warm");
+        // Nothing covers this, so it falls back to the last biome.
+        assert_eq!(name(0.5, -0.5, -0.9), "warm");
+    }
+
+    #[test]
+    fn the_built_in_table_parses() {
+        let biomes = Biomes::parse(BUILT_IN, |_| Some(TextureId(0))).unwrap();
+        assert_eq!(biomes.at(1.0, 0.0, 0.0).name, "peak");
+        assert_eq!(biomes.at(0.2, 0.0, 0.0).name, "grassland");
+    }
+}
@@ -0,0 +1,17 @@
+use super::TerrainGenerator;
+use hexa::coord::Offset;
+
+/// The same height everywhere.
+pub struct Flat {
+    height: f32,
+}
+impl Flat {
+    pub fn new(height: f32) -> Self {
+        Self { height }
+    }
+}
+impl TerrainGenerator for Flat {
+    fn height(&self, _: Offset) -> f32 {
+        self.height
+    }
+}
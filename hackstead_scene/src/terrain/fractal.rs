@@ -0,0 +1,47 @@
+use super::TerrainGenerator;
+use hexa::coord::Offset;
+use noise::{MultiFractal, NoiseFn, Seedable};
+
+/// Hills with smaller hills on them, and smaller ones on those, once for each octave.
+pub struct Fbm {
+    noise: noise::Fbm,
+    /// How many columns apart the largest hills are.
+    scale: f64,
+}
+impl Fbm {
+    pub fn new(seed: u32, scale: f64, octaves: usize) -> Self {
+        Self {
+            noise: noise::Fbm::new().set_seed(seed).set_octaves(octaves),
+            scale,
+        }
+    }
+}
+impl TerrainGenerator for Fbm {
+    fn height(&self, p: Offset) -> f32 {
+        self.noise
+            .get([p.col as f64 / self.scale, p.row as f64 / self.scale]) as f32
+    }
+}
+
+/// Mountain ranges with sharp ridges along their tops.
+pub struct Ridged {
+    noise: noise::RidgedMulti,
+    /// How many columns apart the largest ridges are.
+    scale: f64,
+}
+impl Ridged {
+    pub fn new(seed: u32, scale: f64, octaves: usize) -> Self {
+        Self {
+            noise: noise::RidgedMulti::new()
+                .set_seed(seed)
+                .set_octaves(octaves),
+            scale,
+        }
+    }
+}
+impl TerrainGenerator for Ridged {
+    fn height(&self, p: Offset) -> f32 {
+        self.noise
+            .get([p.col as f64 / self.scale, p.row as f64 / self.scale]) as f32
+    }
+}
@@ -0,0 +1,135 @@
+use super::TerrainGenerator;
+use hexa::{coord::Offset, Tile};
+use image::{GrayImage, Luma};
+use std::path::{Path, PathBuf};
+
+/// Where heightmaps are imported from and exported to: `$HEXA_HEIGHTMAP` if it's set,
+/// otherwise `heightmap.png` in the working directory.
+pub fn heightmap_path() -> PathBuf {
+    std::env::var_os("HEXA_HEIGHTMAP")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("heightmap.png"))
+}
+
+/// Ground shaped like a grayscale image, where each column takes the height of the pixel
+/// at its offset coordinates, `x = col` and `y = row`.
+/// Black is as low as the ground goes and white is as high, and anywhere off the image
+/// is as low as black.
+///
+/// The image is in offset space, one pixel per column, not a picture of the ground from above:
+/// odd rows sit half a hex to the right, and rows are closer together than columns,
+/// so a picture drawn in world space comes out sheared and stretched from top to bottom.
+/// `export` writes images in this same layout, so they come back as the same ground.
+pub struct Heightmap {
+    image: GrayImage,
+}
+impl Heightmap {
+    pub fn new(image: GrayImage) -> Self {
+        Self { image }
+    }
+
+    /// Reads an image from disk, turning it gray if it has any color.
+    pub fn open(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Self::new(image::open(path)?.to_luma()))
+    }
+}
+impl TerrainGenerator for Heightmap {
+    fn height(&self, p: Offset) -> f32 {
+        let (width, height) = self.image.dimensions();
+        if p.col < 0 || p.row < 0 || p.col as u32 >= width || p.row as u32 >= height {
+            return -1.0;
+        }
+
+        let Luma([value]) = *self.image.get_pixel(p.col as u32, p.row as u32);
+        value as f32 / 255.0 * 2.0 - 1.0
+    }
+}
+
+/// The heights of the ground in these columns, as an image `Heightmap` reads back into the
+/// same ground, with one pixel per column in offset space. Only the bottom tile of each column counts, since the tiles stacked on it are
+/// made by the biome table rather than the heightmap.
+/// Heights are divided by `elevation` first, the same way a `TerrainGenerator`'s are scaled
+/// by it. Columns at negative offsets can't be stored, and are left out.
+pub fn export<'a>(columns: impl Iterator<Item = &'a [Tile]>, elevation: f32) -> GrayImage {
+    let grounds = columns
+        .filter_map(|column| column.first())
+        .filter(|tile| tile.position.col >= 0 && tile.position.row >= 0)
+        .collect::<Vec<_>>();
+
+    let width = grounds
+        .iter()
+        .map(|t| t.position.col + 1)
+        .max()
+        .unwrap_or(0);
+    let height = grounds
+        .iter()
+        .map(|t| t.position.row + 1)
+        .max()
+        .unwrap_or(0);
+    let mut image = GrayImage::new(width as u32, height as u32);
+
+    for tile in grounds {
+        let normalized = tile.elevation / elevation.max(std::f32::EPSILON);
+        let value = ((normalized + 1.0) / 2.0 * 255.0)
+            .round()
+            .max(0.0)
+            .min(255.0);
+        image.put_pixel(
+            tile.position.col as u32,
+            tile.position.row as u32,
+            Luma([value as u8]),
+        );
+    }
+
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hexa::TextureId;
+
+    fn column(col: i32, row: i32, elevation: f32) -> Vec<Tile> {
+        vec![Tile {
+            position: Offset::new(col, row),
+            elevation,
+            butt_size: elevation,
+            hat: TextureId(0),
+            butt: TextureId(0),
+            highlight: None,
+        }]
+    }
+
+    #[test]
+    fn exported_heights_come_back() {
+        let elevation = 2.5;
+        let heights = (0..4)
+            .flat_map(|row| (0..5).map(move |col| Offset::new(col, row)))
+            .enumerate()
+            .map(|(i, p)| (p, (i as f32 * 0.37).sin()))
+            .collect::<Vec<_>>();
+        let columns = heights
+            .iter()
+            .map(|&(p, h)| column(p.col, p.row, h * elevation))
+            .chain(std::iter::once(column(-1, 2, elevation)))
+            .collect::<Vec<_>>();
+
+        let image = export(columns.iter().map(|c| &c[..]), elevation);
+        assert_eq!(image.dimensions(), (5, 4));
+
+        let heightmap = Heightmap::new(image);
+        let grey_level = 2.0 / 255.0;
+        for &(p, h) in &heights {
+            let back = heightmap.height(p);
+            assert!(
+                (back - h).abs() <= grey_level,
+                "{:?}: {} became {}",
+                p,
+                h,
+                back
+            );
+        }
+        assert_eq!(heightmap.height(Offset::new(5, 0)), -1.0);
+        assert_eq!(heightmap.height(Offset::new(-1, 2)), -1.0);
+    }
+}
@@ -0,0 +1,141 @@
+//! Ways of shaping the ground, all turned into stacks of tiles the same way.
+use crate::controls::tiling::Data;
+use hexa::{chunk::ChunkCoord, coord::Offset, Tile};
+
+pub mod biome;
+mod flat;
+mod fractal;
+pub mod heightmap;
+mod perlin;
+mod voronoi;
+pub use biome::Biomes;
+use biome::Climate;
+pub use flat::Flat;
+pub use fractal::{Fbm, Ridged};
+pub use heightmap::Heightmap;
+pub use perlin::Perlin;
+pub use voronoi::Voronoi;
+
+/// How far above the water beaches are.
+const BEACH_HEIGHT: f32 = 0.02;
+
+/// Says how high the ground is in each column.
+pub trait TerrainGenerator {
+    /// How high the ground is here, from about -1.0 to 1.0.
+    /// This is scaled by the elevation before any tiles are made.
+    fn height(&self, position: Offset) -> f32;
+
+    /// The tiles in a chunk, made out of the heights of its columns
+    /// and stacked out of the layers of whichever biome each column is in.
+    /// Ground lower than the sea level is left underwater, and ground just above it is
+    /// flattened into beaches, so the shores of each island are smooth.
+    fn generate_chunk(&self, chunk: ChunkCoord, data: &Data, biomes: &Biomes) -> Vec<Vec<Tile>> {
+        let e = data.elevation;
+        let sea = data.sea_level;
+        let beach = (e * 0.1).max(BEACH_HEIGHT);
+        // every column reaches down to here, so there are no gaps between them underwater
+        let floor = sea.min(-e) - 0.3;
+        let climate = Climate::new(data.seed, data.size as f64 * 2.0);
+        let in_bounds = |p: Offset| {
+            data.infinite
+                || (0..data.size as i32).contains(&p.col) && (0..data.size as i32).contains(&p.row)
+        };
+
+        chunk
+            .offsets()
+            .filter(|&p| in_bounds(p))
+            .map(|position| {
+                let height = self.height(position) * e;
+                let top = if height > sea && height < sea + beach {
+                    sea + BEACH_HEIGHT
+                } else {
+                    height
+                };
+
+                let (moisture, temperature) = climate.at(position);
+                let biome = biomes.at(
+                    (height - sea) / e.max(std::f32::EPSILON),
+                    moisture,
+                    temperature,
+                );
+
+                biome
+                    .layers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, layer)| Tile {
+                        position,
+                        elevation: top,
+                        hat: layer.hat,
+                        butt: layer.butt,
+                        butt_size: match i {
+                            0 => top - floor,
+                            _ => layer.thickness * height * height,
+                        },
+                        highlight: None,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Each kind of TerrainGenerator that can be picked from the Tiling tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Generator {
+    Perlin,
+    Fbm,
+    Ridged,
+    Voronoi,
+    Flat,
+    /// Imported from the image at `heightmap::heightmap_path()`.
+    /// Like the others, only `size` columns of it are used unless `infinite` is on.
+    Heightmap,
+}
+impl Generator {
+    pub const ALL: &'static [Generator] = &[
+        Generator::Perlin,
+        Generator::Fbm,
+        Generator::Ridged,
+        Generator::Voronoi,
+        Generator::Flat,
+        Generator::Heightmap,
+    ];
+
+    /// Whether this kind of generator is made of several octaves of noise.
+    pub fn has_octaves(self) -> bool {
+        match self {
+            Generator::Fbm | Generator::Ridged => true,
+            _ => false,
+        }
+    }
+
+    /// The generator of this kind, set up with the seed, size and octaves from `data`.
+    /// A heightmap that can't be imported is logged, and flat ground is made instead.
+    pub fn build(self, data: &Data) -> Box<dyn TerrainGenerator> {
+        let scale = data.size as f64;
+        match self {
+            Generator::Perlin => Box::new(Perlin::new(data.seed, scale)),
+            Generator::Fbm => Box::new(Fbm::new(data.seed, scale, data.octaves as usize)),
+            Generator::Ridged => Box::new(Ridged::new(data.seed, scale, data.octaves as usize)),
+            Generator::Voronoi => Box::new(Voronoi::new(data.seed, scale)),
+            // low enough to stay above the beaches without being iced over
+            Generator::Flat => Box::new(Flat::new(0.25)),
+            Generator::Heightmap => {
+                let path = heightmap::heightmap_path();
+                match Heightmap::open(&path) {
+                    Ok(heightmap) => Box::new(heightmap),
+                    Err(e) => {
+                        log::error!("can't import heightmap {}: {}", path.display(), e);
+                        Generator::Flat.build(data)
+                    }
+                }
+            }
+        }
+    }
+}
+impl Default for Generator {
+    fn default() -> Self {
+        Generator::Perlin
+    }
+}
@@ -0,0 +1,24 @@
+use super::TerrainGenerator;
+use hexa::coord::Offset;
+use noise::{NoiseFn, Seedable};
+
+/// Smooth, rolling hills.
+pub struct Perlin {
+    noise: noise::Perlin,
+    /// How many columns apart the hills are.
+    scale: f64,
+}
+impl Perlin {
+    pub fn new(seed: u32, scale: f64) -> Self {
+        Self {
+            noise: noise::Perlin::new().set_seed(seed),
+            scale,
+        }
+    }
+}
+impl TerrainGenerator for Perlin {
+    fn height(&self, p: Offset) -> f32 {
+        self.noise
+            .get([p.col as f64 / self.scale, p.row as f64 / self.scale]) as f32
+    }
+}
@@ -0,0 +1,25 @@
+use super::TerrainGenerator;
+use hexa::coord::Offset;
+use noise::{NoiseFn, Seedable};
+
+/// Flat-topped plateaus, each at its own height, meeting in cliffs.
+pub struct Voronoi {
+    /// Without range enabled, Worley noise gives every point in a cell the same value.
+    noise: noise::Worley,
+    /// How many columns across each plateau is.
+    scale: f64,
+}
+impl Voronoi {
+    pub fn new(seed: u32, scale: f64) -> Self {
+        Self {
+            noise: noise::Worley::new().set_seed(seed).enable_range(false),
+            scale,
+        }
+    }
+}
+impl TerrainGenerator for Voronoi {
+    fn height(&self, p: Offset) -> f32 {
+        self.noise
+            .get([p.col as f64 / self.scale, p.row as f64 / self.scale]) as f32
+    }
+}
@@ -0,0 +1,162 @@
+use hexa::chunk::ChunkCoord;
+use hexa::coord::Offset;
+use hexa::{Renderer, Tile};
+use std::collections::HashMap;
+
+/// Generating and uploading a chunk takes a moment, so only this many are loaded per update.
+const MAX_LOADS_PER_UPDATE: usize = 4;
+
+/// The tiles of every chunk that's currently loaded, kept in sync with what the renderer has.
+pub struct World {
+    chunks: HashMap<ChunkCoord, Vec<Vec<Tile>>>,
+    /// Chunks whose tiles were given explicitly, say by loading a map, instead of generated.
+    /// Whenever one of these chunks is loaded, these tiles are used for it.
+    fixed: HashMap<ChunkCoord, Vec<Vec<Tile>>>,
+    /// Columns edited by hand, used instead of whatever's generated or fixed there.
+    /// Unlike everything else, these are kept when the world is cleared.
+    edits: HashMap<Offset, Vec<Tile>>,
+    /// Chunks no more than this many chunks from the center are loaded.
+    pub load_radius: u32,
+    /// Chunks further than this many chunks from the center are evicted.
+    /// Larger than the load radius, so walking back and forth over a border doesn't thrash.
+    pub evict_radius: u32,
+}
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            chunks: HashMap::new(),
+            fixed: HashMap::new(),
+            edits: HashMap::new(),
+            load_radius: 2,
+            evict_radius: 3,
+        }
+    }
+}
+impl World {
+    /// Forgets every chunk, fixed ones included, so that they'll all be generated again.
+    /// Columns edited by hand are kept, and put back over the new tiles.
+    pub fn clear(&mut self, renderer: &mut dyn Renderer) {
+        self.chunks.clear();
+        self.fixed.clear();
+        renderer.set_tiles(Vec::new());
+    }
+
+    /// Forgets the columns edited by hand, say because a map with its own edits is being loaded.
+    pub fn forget_edits(&mut self) {
+        self.edits.clear();
+    }
+
+    /// Uses these columns instead of generating the chunks they're in.
+    /// Chunks that are already loaded aren't changed, so this is best done right after `clear`.
+    pub fn fix(&mut self, columns: impl IntoIterator<Item = Vec<Tile>>) {
+        for column in columns {
+            if let Some(first) = column.first() {
+                self.fixed
+                    .entry(ChunkCoord::containing(first.position))
+                    .or_insert_with(Vec::new)
+                    .push(column);
+            }
+        }
+    }
+
+    /// Loads chunks around `center` that aren't loaded yet, using `generate` to make the tiles
+    /// of those that aren't fixed, and evicts the ones that have gotten too far away.
+    pub fn stream(
+        &mut self,
+        center: Offset,
+        renderer: &mut dyn Renderer,
+        generate: impl Fn(ChunkCoord) -> Vec<Vec<Tile>>,
+    ) {
+        let center = ChunkCoord::containing(center);
+
+        let evict_radius = self.evict_radius.max(self.load_radius);
+        let far = self
+            .chunks
+            .keys()
+            .filter(|c| c.distance(center) > evict_radius)
+            .copied()
+            .collect::<Vec<_>>();
+        for chunk in far {
+            log::debug!("evicting chunk {:?}", chunk);
+            self.chunks.remove(&chunk);
+            renderer.remove_chunk(chunk);
+        }
+
+        let missing = center
+            .around(self.load_radius)
+            .into_iter()
+            .filter(|c| !self.chunks.contains_key(c))
+            .take(MAX_LOADS_PER_UPDATE)
+            .collect::<Vec<_>>();
+        for chunk in missing {
+            log::debug!("loading chunk {:?}", chunk);
+            let mut tiles = self
+                .fixed
+                .get(&chunk)
+                .cloned()
+                .unwrap_or_else(|| generate(chunk));
+            if !self.edits.is_empty() {
+                for column in &mut tiles {
+                    let edit = column.first().and_then(|t| self.edits.get(&t.position));
+                    if let Some(edit) = edit {
+                        *column = edit.clone();
+                    }
+                }
+            }
+            renderer.set_chunk(chunk, tiles.clone());
+            self.chunks.insert(chunk, tiles);
+        }
+    }
+
+    /// The column at this position, if its chunk is loaded.
+    pub fn column(&self, p: Offset) -> Option<&[Tile]> {
+        self.chunks
+            .get(&ChunkCoord::containing(p))?
+            .iter()
+            .find(|column| column.first().map(|t| t.position) == Some(p))
+            .map(|column| &column[..])
+    }
+
+    /// Replaces these columns, which must be in loaded chunks; any that aren't, or are empty,
+    /// are left out. They're remembered as edits, so the changes aren't lost when their chunks
+    /// are evicted or the world is cleared. Returns the columns that were replaced,
+    /// as they were before.
+    pub fn set_columns(&mut self, columns: &[(Offset, Vec<Tile>)]) -> Vec<(Offset, Vec<Tile>)> {
+        let mut old = Vec::new();
+        for (p, column) in columns.iter().filter(|(_, c)| !c.is_empty()) {
+            let chunk = ChunkCoord::containing(*p);
+            let chunk_columns = match self.chunks.get_mut(&chunk) {
+                Some(chunk_columns) => chunk_columns,
+                None => continue,
+            };
+            let i = match chunk_columns
+                .iter()
+                .position(|c| c.first().map(|t| t.position) == Some(*p))
+            {
+                Some(i) => i,
+                None => continue,
+            };
+
+            old.push((*p, std::mem::replace(&mut chunk_columns[i], column.clone())));
+            self.edits.insert(*p, column.clone());
+        }
+        old
+    }
+
+    /// Every loaded column of tiles.
+    pub fn columns(&self) -> impl Iterator<Item = &[Tile]> {
+        self.chunks
+            .values()
+            .flat_map(|columns| columns.iter().map(|column| &column[..]))
+    }
+
+    /// Every loaded column of tiles, and every column edited by hand that isn't loaded.
+    pub fn columns_and_edits(&self) -> impl Iterator<Item = &[Tile]> {
+        self.columns().chain(
+            self.edits
+                .iter()
+                .filter(move |(p, _)| self.column(**p).is_none())
+                .map(|(_, column)| &column[..]),
+        )
+    }
+}
@@ -53,4 +53,29 @@ impl Camera {
     pub fn build_view_projection_matrix(&self) -> nalgebra::Matrix4<f32> {
         return self.projection() * self.view();
     }
+
+    /// The ray passing from the camera through the given point on a screen of the given size.
+    /// `cursor` and `screen` only need to be in the same units, logical or physical.
+    pub fn ray(&self, screen: iced_winit::Size, cursor: nalgebra::Point2<f32>) -> crate::pick::Ray {
+        let ndc = nalgebra::Vector2::new(
+            cursor.x / screen.width * 2.0 - 1.0,
+            1.0 - cursor.y / screen.height * 2.0,
+        );
+        let inverse = self
+            .build_view_projection_matrix()
+            .try_inverse()
+            .unwrap_or_else(nalgebra::Matrix4::identity);
+        let unproject = |z| {
+            nalgebra::Point3::from_homogeneous(
+                inverse * nalgebra::Vector4::new(ndc.x, ndc.y, z, 1.0),
+            )
+            .unwrap_or_else(nalgebra::Point3::origin)
+        };
+
+        let near = unproject(-1.0);
+        crate::pick::Ray {
+            origin: near,
+            direction: unproject(1.0) - near,
+        }
+    }
 }
@@ -0,0 +1,54 @@
+//! Splitting the world up into square chunks of offset coordinates,
+//! so that it can be generated, uploaded and thrown away a piece at a time.
+use crate::coord::Offset;
+
+/// How many columns and rows of tiles are in a chunk.
+/// Even, so that every chunk starts on an even row and odd rows stay odd within chunks.
+pub const CHUNK_SIZE: i32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub y: i32,
+}
+impl ChunkCoord {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The chunk a tile at this position belongs to.
+    pub fn containing(p: Offset) -> Self {
+        Self::new(p.col.div_euclid(CHUNK_SIZE), p.row.div_euclid(CHUNK_SIZE))
+    }
+
+    /// The position of the tile in the lowest column and row of this chunk.
+    pub fn origin(self) -> Offset {
+        Offset::new(self.x * CHUNK_SIZE, self.y * CHUNK_SIZE)
+    }
+
+    /// Every position in this chunk, a column at a time.
+    pub fn offsets(self) -> impl Iterator<Item = Offset> {
+        let origin = self.origin();
+        (0..CHUNK_SIZE).flat_map(move |col| {
+            (0..CHUNK_SIZE).map(move |row| Offset::new(origin.col + col, origin.row + row))
+        })
+    }
+
+    /// How many chunks away the other chunk is, counting diagonals as one step.
+    pub fn distance(self, other: ChunkCoord) -> u32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs()) as u32
+    }
+
+    /// Every chunk no more than `radius` chunks away, nearest first.
+    pub fn around(self, radius: u32) -> Vec<ChunkCoord> {
+        let r = radius as i32;
+        let mut chunks = (-r..=r)
+            .flat_map(|x| (-r..=r).map(move |y| ChunkCoord::new(self.x + x, self.y + y)))
+            .collect::<Vec<_>>();
+        chunks.sort_by_key(|&c| {
+            let (dx, dy) = (c.x - self.x, c.y - self.y);
+            dx * dx + dy * dy
+        });
+        chunks
+    }
+}
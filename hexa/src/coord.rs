@@ -0,0 +1,355 @@
+//! Coordinate systems for our pointy-topped hexagons.
+//!
+//! Hexes have a radius of 1.0, so neighbors on the same row are `sqrt(3)` apart,
+//! and rows are 1.5 apart. Offset coordinates use the "odd-r" layout,
+//! where odd rows are shoved half a hex to the right.
+//! Axial and cube coordinates are where the actual math happens,
+//! see https://www.redblobgames.com/grids/hexagons/ for the details.
+use nalgebra::Vector2;
+
+/// The distance between the centers of two neighbors on the same row.
+pub fn hex_width() -> f32 {
+    3.0_f32.sqrt()
+}
+/// The distance between the centers of two rows.
+pub const ROW_HEIGHT: f32 = 1.5;
+
+/// A column and a row, in the "odd-r" layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Offset {
+    pub col: i32,
+    pub row: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Axial {
+    pub q: i32,
+    pub r: i32,
+}
+
+/// Cube coordinates always satisfy `x + y + z == 0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Cube {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Offset {
+    pub fn new(col: i32, row: i32) -> Self {
+        Self { col, row }
+    }
+
+    pub fn to_axial(self) -> Axial {
+        self.into()
+    }
+
+    /// The center of this hex, in world space.
+    pub fn to_world(self) -> Vector2<f32> {
+        self.to_axial().to_world()
+    }
+}
+
+impl Axial {
+    /// Starts with the hex to the right and goes counterclockwise.
+    pub const DIRECTIONS: [Axial; 6] = [
+        Axial { q: 1, r: 0 },
+        Axial { q: 1, r: -1 },
+        Axial { q: 0, r: -1 },
+        Axial { q: -1, r: 0 },
+        Axial { q: -1, r: 1 },
+        Axial { q: 0, r: 1 },
+    ];
+
+    pub fn new(q: i32, r: i32) -> Self {
+        Self { q, r }
+    }
+
+    pub fn to_cube(self) -> Cube {
+        self.into()
+    }
+
+    pub fn to_offset(self) -> Offset {
+        self.into()
+    }
+
+    /// The neighbor in one of the six `DIRECTIONS`; the index wraps around.
+    pub fn neighbor(self, direction: usize) -> Axial {
+        self + Self::DIRECTIONS[direction % 6]
+    }
+
+    pub fn neighbors(self) -> [Axial; 6] {
+        let mut n = Self::DIRECTIONS;
+        for d in n.iter_mut() {
+            *d = self + *d;
+        }
+        n
+    }
+
+    /// How many steps it takes to walk from one hex to the other.
+    pub fn distance(self, other: Axial) -> u32 {
+        self.to_cube().distance(other.to_cube())
+    }
+
+    /// The hexes exactly `radius` steps away, going counterclockwise.
+    /// A radius of zero is just this hex.
+    pub fn ring(self, radius: u32) -> Vec<Axial> {
+        if radius == 0 {
+            return vec![self];
+        }
+
+        let mut hex = self + Self::DIRECTIONS[4] * radius as i32;
+        let mut ring = Vec::with_capacity(6 * radius as usize);
+        for direction in 0..6 {
+            for _ in 0..radius {
+                ring.push(hex);
+                hex = hex.neighbor(direction);
+            }
+        }
+        ring
+    }
+
+    /// Every hex no more than `radius` steps away, starting at this hex and spiraling outward.
+    pub fn spiral(self, radius: u32) -> Vec<Axial> {
+        (0..=radius).flat_map(|r| self.ring(r)).collect()
+    }
+
+    /// The hexes a straight line from this hex to the other passes through, including both ends.
+    pub fn line(self, other: Axial) -> Vec<Axial> {
+        let n = self.distance(other);
+        // nudging the ends keeps the line from landing exactly on edges between hexes
+        let (a, b) = (self.to_fractional(1e-6), other.to_fractional(1e-6));
+
+        (0..=n)
+            .map(|i| {
+                let t = if n == 0 { 0.0 } else { i as f32 / n as f32 };
+                Axial::round(a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t)
+            })
+            .collect()
+    }
+
+    fn to_fractional(self, nudge: f32) -> Vector2<f32> {
+        Vector2::new(self.q as f32 + nudge, self.r as f32 + nudge)
+    }
+
+    /// The hex containing the fractional axial coordinates `q` and `r`.
+    pub fn round(q: f32, r: f32) -> Axial {
+        Cube::round(q, -q - r, r).into()
+    }
+
+    /// The center of this hex, in world space.
+    pub fn to_world(self) -> Vector2<f32> {
+        Vector2::new(
+            hex_width() * (self.q as f32 + self.r as f32 / 2.0),
+            ROW_HEIGHT * self.r as f32,
+        )
+    }
+
+    /// The hex containing this point in world space.
+    pub fn from_world(p: Vector2<f32>) -> Axial {
+        let r = p.y / ROW_HEIGHT;
+        let q = p.x / hex_width() - r / 2.0;
+        Axial::round(q, r)
+    }
+}
+
+impl Cube {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        debug_assert_eq!(x + y + z, 0, "cube coordinates must sum to zero");
+        Self { x, y, z }
+    }
+
+    pub fn to_axial(self) -> Axial {
+        self.into()
+    }
+
+    pub fn distance(self, other: Cube) -> u32 {
+        let d = self - other;
+        ((d.x.abs() + d.y.abs() + d.z.abs()) / 2) as u32
+    }
+
+    /// The hex containing the fractional cube coordinates, which should sum to about zero.
+    pub fn round(x: f32, y: f32, z: f32) -> Cube {
+        let (mut rx, mut ry, mut rz) = (x.round(), y.round(), z.round());
+        let (dx, dy, dz) = ((rx - x).abs(), (ry - y).abs(), (rz - z).abs());
+
+        // whichever component was rounded the most is recomputed from the other two,
+        // so the result still sums to zero
+        if dx > dy && dx > dz {
+            rx = -ry - rz;
+        } else if dy > dz {
+            ry = -rx - rz;
+        } else {
+            rz = -rx - ry;
+        }
+
+        Cube::new(rx as i32, ry as i32, rz as i32)
+    }
+}
+
+impl From<Offset> for Axial {
+    fn from(Offset { col, row }: Offset) -> Self {
+        Axial {
+            q: col - (row - (row & 1)) / 2,
+            r: row,
+        }
+    }
+}
+impl From<Axial> for Offset {
+    fn from(Axial { q, r }: Axial) -> Self {
+        Offset {
+            col: q + (r - (r & 1)) / 2,
+            row: r,
+        }
+    }
+}
+impl From<Axial> for Cube {
+    fn from(Axial { q, r }: Axial) -> Self {
+        Cube::new(q, -q - r, r)
+    }
+}
+impl From<Cube> for Axial {
+    fn from(Cube { x, z, .. }: Cube) -> Self {
+        Axial { q: x, r: z }
+    }
+}
+impl From<Cube> for Offset {
+    fn from(c: Cube) -> Self {
+        Axial::from(c).into()
+    }
+}
+impl From<Offset> for Cube {
+    fn from(o: Offset) -> Self {
+        Axial::from(o).into()
+    }
+}
+
+impl std::ops::Add for Axial {
+    type Output = Axial;
+
+    fn add(self, o: Axial) -> Axial {
+        Axial::new(self.q + o.q, self.r + o.r)
+    }
+}
+impl std::ops::Sub for Axial {
+    type Output = Axial;
+
+    fn sub(self, o: Axial) -> Axial {
+        Axial::new(self.q - o.q, self.r - o.r)
+    }
+}
+impl std::ops::Mul<i32> for Axial {
+    type Output = Axial;
+
+    fn mul(self, k: i32) -> Axial {
+        Axial::new(self.q * k, self.r * k)
+    }
+}
+impl std::ops::Sub for Cube {
+    type Output = Cube;
+
+    fn sub(self, o: Cube) -> Cube {
+        Cube::new(self.x - o.x, self.y - o.y, self.z - o.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn offsets() -> impl Iterator<Item = Offset> {
+        (-6..=6).flat_map(|col| (-6..=6).map(move |row| Offset::new(col, row)))
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for o in offsets() {
+            let a = o.to_axial();
+            assert_eq!(a.to_offset(), o);
+            assert_eq!(a.to_cube().to_axial(), a);
+            assert_eq!(Offset::from(Cube::from(o)), o);
+
+            let c = a.to_cube();
+            assert_eq!(c.x + c.y + c.z, 0);
+        }
+    }
+
+    #[test]
+    fn odd_rows_are_shoved_right() {
+        assert_eq!(Offset::new(0, 1).to_world().x, hex_width() / 2.0);
+        assert_eq!(Offset::new(0, -1).to_world().x, hex_width() / 2.0);
+        assert_eq!(Offset::new(0, 2).to_world().x, 0.0);
+    }
+
+    #[test]
+    fn world_round_trip() {
+        for o in offsets() {
+            let center = o.to_world();
+            assert_eq!(Axial::from_world(center).to_offset(), o);
+
+            // anywhere well inside the hex is still in it
+            for d in &Axial::DIRECTIONS {
+                let toward = d.to_world() * 0.4;
+                assert_eq!(Axial::from_world(center + toward).to_offset(), o);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_are_one_apart() {
+        let a = Axial::new(2, -3);
+        for (i, n) in a.neighbors().iter().enumerate() {
+            assert_eq!(a.distance(*n), 1);
+            assert_eq!(a.neighbor(i), *n);
+            assert_eq!(a.neighbor(i + 6), *n);
+            assert!(((a.to_world() - n.to_world()).norm() - hex_width()).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn rings_have_six_n_cells() {
+        let center = Axial::new(1, 2);
+        assert_eq!(center.ring(0), vec![center]);
+        for radius in 1..=5 {
+            let ring = center.ring(radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            assert_eq!(ring.iter().collect::<HashSet<_>>().len(), ring.len());
+            assert!(ring.iter().all(|h| center.distance(*h) == radius));
+        }
+    }
+
+    #[test]
+    fn spirals_have_every_cell_within_radius() {
+        let center = Axial::new(-3, 1);
+        for radius in 0..=5 {
+            let spiral = center.spiral(radius);
+            let n = radius as usize;
+            assert_eq!(spiral.len(), 1 + 3 * n * (n + 1));
+            assert_eq!(spiral[0], center);
+            assert_eq!(spiral.iter().collect::<HashSet<_>>().len(), spiral.len());
+            assert!(spiral.iter().all(|h| center.distance(*h) <= radius));
+        }
+    }
+
+    #[test]
+    fn lines_are_connected_and_end_at_both_hexes() {
+        let a = Axial::new(0, 0);
+        for b in a.spiral(6) {
+            let line = a.line(b);
+            assert_eq!(line.len(), a.distance(b) as usize + 1);
+            assert_eq!(line[0], a);
+            assert_eq!(*line.last().unwrap(), b);
+            assert!(line.windows(2).all(|w| w[0].distance(w[1]) == 1));
+        }
+    }
+
+    #[test]
+    fn rounding_picks_the_nearest_hex() {
+        assert_eq!(Axial::round(0.0, 0.0), Axial::new(0, 0));
+        assert_eq!(Axial::round(0.9, 0.05), Axial::new(1, 0));
+        assert_eq!(Axial::round(-0.1, 1.1), Axial::new(0, 1));
+        assert_eq!(Cube::round(0.4, -0.3, -0.1), Cube::new(0, 0, 0));
+        assert_eq!(Cube::round(1.2, -0.8, -0.4), Cube::new(1, -1, 0));
+    }
+}
@@ -0,0 +1,35 @@
+//! Figuring out what a camera can see, so what it can't see needn't be drawn.
+use nalgebra::{Matrix4, Point3, Vector4};
+
+/// The six planes bounding everything a view projection matrix puts on screen.
+/// Each plane is stored as `(a, b, c, d)`, where `ax + by + cz + d` is the signed distance
+/// of the point `(x, y, z)` from the plane, positive on the side facing the inside.
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+impl Frustum {
+    /// Pulls the planes straight out of the rows of the matrix,
+    /// following Gribb and Hartmann's "Fast Extraction of Viewing Frustum Planes".
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let row = |i| m.row(i).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let mut planes = [w + x, w - x, w + y, w - y, w + z, w - z];
+        for plane in planes.iter_mut() {
+            let length = plane.xyz().norm();
+            if length > 0.0 {
+                *plane /= length;
+            }
+        }
+
+        Self { planes }
+    }
+
+    /// Whether any part of the sphere might be visible.
+    pub fn intersects_sphere(&self, center: &Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|p| p.xyz().dot(&center.coords) + p.w >= -radius)
+    }
+}
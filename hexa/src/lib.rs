@@ -5,32 +5,194 @@ pub use nalgebra as na;
 use iced_wgpu::Primitive as GuiPrimitive;
 pub use iced_wgpu::Renderer as IcedRenderer;
 pub use iced_winit::Debug as IcedDebug;
-use iced_winit::{mouse, winit, Size};
+use iced_winit::{mouse, winit, Color, Size};
 use winit::event::{ModifiersState, WindowEvent};
 
 pub mod camera;
 pub use camera::Camera;
+pub mod chunk;
+pub mod coord;
+pub mod frustum;
+pub mod paths;
+pub mod pick;
 
+/// A handle to one of the textures the renderer has loaded,
+/// found by name with Renderer::tile_texture or Renderer::sprite_texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct TextureId(pub u32);
+
+#[derive(Debug, Clone)]
 pub struct Tile {
-    pub position: nalgebra::Vector2<u32>,
+    pub position: coord::Offset,
     pub elevation: f32,
     pub butt_size: f32,
-    pub hat: u32,
-    pub butt: u32,
+    pub hat: TextureId,
+    pub butt: TextureId,
+    pub highlight: Option<Highlight>,
+}
+impl Tile {
+    /// Tiles in a column are stacked on top of one another, so each tile after the first
+    /// is raised by its own butt_size and that of every tile between it and the first.
+    /// This yields the resulting elevation of each tile, in world space.
+    pub fn stack_elevations<'a>(column: &'a [Tile]) -> impl Iterator<Item = f32> + 'a {
+        column.iter().enumerate().scan(0.0_f32, |a, (i, tile)| {
+            if i > 0 {
+                *a += tile.butt_size;
+            }
+            Some(tile.elevation + *a)
+        })
+    }
+}
+
+/// Draws attention to a tile without changing its textures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Highlight {
+    /// Blended over the tile's textures, by however much its alpha is.
+    pub tint: Color,
+    /// Drawn around the edges of each face of the tile.
+    pub outline: Option<Color>,
+}
+impl Highlight {
+    pub const HOVERED: Highlight = Highlight {
+        tint: Color {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 0.2,
+        },
+        outline: None,
+    };
+    pub const SELECTED: Highlight = Highlight {
+        tint: Color {
+            r: 0.203,
+            g: 0.745,
+            b: 0.356,
+            a: 0.3,
+        },
+        outline: Some(Color {
+            r: 0.94,
+            g: 0.94,
+            b: 1.0,
+            a: 1.0,
+        }),
+    };
+    pub const INVALID: Highlight = Highlight {
+        tint: Color {
+            r: 0.9,
+            g: 0.1,
+            b: 0.1,
+            a: 0.45,
+        },
+        outline: Some(Color {
+            r: 0.5,
+            g: 0.0,
+            b: 0.0,
+            a: 1.0,
+        }),
+    };
+}
+
+/// A sun shining from far away in one direction, plus light that comes from everywhere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lighting {
+    /// The direction sunlight travels in, in world space. Needn't be normalized.
+    pub sun_direction: nalgebra::Vector3<f32>,
+    pub sun_color: Color,
+    /// Light that reaches every face, whichever way it's facing.
+    pub ambient: Color,
+    /// Shades each face of a tile evenly, instead of smoothly around its sides,
+    /// so that the edges of cliffs stand out.
+    pub flat_shading: bool,
+}
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            sun_direction: nalgebra::Vector3::new(-0.5, -0.3, -1.0),
+            sun_color: Color {
+                r: 0.75,
+                g: 0.72,
+                b: 0.66,
+                a: 1.0,
+            },
+            ambient: Color {
+                r: 0.38,
+                g: 0.4,
+                b: 0.45,
+                a: 1.0,
+            },
+            flat_shading: true,
+        }
+    }
+}
+
+/// What shows behind the tiles: a gradient from the horizon up to the point straight overhead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sky {
+    pub zenith: Color,
+    /// Anything below the horizon is this color too.
+    pub horizon: Color,
 }
 
+#[derive(Debug, Clone)]
 pub struct Sprite {
-    pub image: u32,
+    pub image: TextureId,
     pub position: nalgebra::Vector2<f32>,
     pub scale: nalgebra::Vector2<f32>,
 }
 
+/// How many instances the last frame drew, and how many were left out for being off screen.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub tiles_drawn: usize,
+    pub tiles_culled: usize,
+    pub sprites_drawn: usize,
+    pub sprites_culled: usize,
+}
+
 /// This trait specifies the methods that Scenes have access to.
 pub trait Renderer {
     fn screen_size(&self) -> Size;
     fn set_tiles(&mut self, tiles: Vec<Vec<Tile>>);
+    /// Replaces the stacks of tiles at each of these positions, leaving the rest alone;
+    /// far cheaper than set_tiles when only a few columns change. An empty stack removes a column.
+    fn update_tiles(&mut self, columns: Vec<(coord::Offset, Vec<Tile>)>);
+    /// Replaces every column in a chunk with the given ones, leaving the rest of the world alone.
+    /// Any positions in the chunk not given a column are left empty.
+    fn set_chunk(&mut self, chunk: chunk::ChunkCoord, tiles: Vec<Vec<Tile>>) {
+        let mut columns = chunk
+            .offsets()
+            .map(|p| (p, Vec::new()))
+            .collect::<std::collections::HashMap<_, _>>();
+        for column in tiles {
+            if let Some(position) = column.first().map(|t| t.position) {
+                columns.insert(position, column);
+            }
+        }
+        self.update_tiles(columns.into_iter().collect());
+    }
+    /// Removes every column in a chunk.
+    fn remove_chunk(&mut self, chunk: chunk::ChunkCoord) {
+        self.update_tiles(chunk.offsets().map(|p| (p, Vec::new())).collect());
+    }
+    /// Highlights every tile in the columns at these positions, taking precedence over the
+    /// highlights the tiles themselves came with. Replaces the previous set of highlights.
+    fn set_highlights(&mut self, highlights: Vec<(coord::Offset, Highlight)>);
     fn set_sprites(&mut self, sprites: Vec<Sprite>);
     fn set_camera(&mut self, camera: &Camera);
+    fn set_lighting(&mut self, lighting: &Lighting);
+    /// Draws this sky behind everything, instead of the renderer's plain background color.
+    fn set_sky(&mut self, sky: &Sky);
+    /// Fills everything below this height with water, or drains it all for None.
+    fn set_sea_level(&mut self, sea_level: Option<f32>);
+    fn render_stats(&self) -> RenderStats;
+    /// The tile texture loaded from the image with this file stem, e.g. "snow_hat".
+    fn tile_texture(&self, name: &str) -> Option<TextureId>;
+    /// The sprite texture loaded from the image with this file stem, e.g. "stump".
+    fn sprite_texture(&self, name: &str) -> Option<TextureId>;
+    /// The file stem of the image a tile texture was loaded from, the opposite of tile_texture.
+    fn tile_texture_name(&self, id: TextureId) -> Option<&str>;
+    /// The file stem of the image a sprite texture was loaded from.
+    fn sprite_texture_name(&self, id: TextureId) -> Option<&str>;
     fn iced_mut(&mut self) -> (&mut IcedRenderer, &mut IcedDebug);
 }
 
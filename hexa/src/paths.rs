@@ -0,0 +1,13 @@
+//! Finding files that ship alongside the executable, rather than wherever it was built.
+use std::path::PathBuf;
+
+/// The first of `names` found in the directory the executable is in, or failing that in one
+/// of the directories above it. An installed build finds what's installed next to it,
+/// and one run from `target/debug` finds what's at the root of the workspace.
+pub fn beside_executable(names: &[&str]) -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    exe.ancestors()
+        .skip(1)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|path| path.exists())
+}
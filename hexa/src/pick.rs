@@ -0,0 +1,214 @@
+//! Finding which tile is under the cursor.
+use crate::{coord::Offset, Tile};
+use nalgebra::{Point3, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// Not necessarily normalized.
+    pub direction: Vector3<f32>,
+}
+impl Ray {
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub position: Offset,
+    /// Which tile in the stack at `position` was hit, zero being the bottom one.
+    pub layer: usize,
+    /// Where the ray struck the tile, in world space.
+    pub point: Point3<f32>,
+}
+
+/// The distance from the center of a hex to the middle of one of its sides.
+fn apothem() -> f32 {
+    3.0_f32.sqrt() / 2.0
+}
+
+/// Where the ray enters the hexagonal prism centered on `center` spanning `bottom..top`,
+/// as a multiple of its direction, treating the prism as the intersection of eight half-spaces.
+fn enter_prism(ray: &Ray, center: nalgebra::Vector2<f32>, bottom: f32, top: f32) -> Option<f32> {
+    let (mut near, mut far) = (std::f32::NEG_INFINITY, std::f32::INFINITY);
+
+    let sides = (0..6).map(|i| {
+        let (sin, cos) = (i as f32 * std::f32::consts::PI / 3.0).sin_cos();
+        let normal = Vector3::new(cos, sin, 0.0);
+        (normal, normal.xy().dot(&center) + apothem())
+    });
+    let caps = vec![(Vector3::z(), top), (-Vector3::z(), -bottom)];
+
+    for (normal, distance) in sides.chain(caps) {
+        let denom = normal.dot(&ray.direction);
+        let dist = distance - normal.dot(&ray.origin.coords);
+
+        if denom.abs() < std::f32::EPSILON {
+            // parallel to this plane; either always inside it or never
+            if dist < 0.0 {
+                return None;
+            }
+        } else if denom < 0.0 {
+            near = near.max(dist / denom);
+        } else {
+            far = far.min(dist / denom);
+        }
+    }
+
+    if near <= far && far >= 0.0 {
+        Some(near.max(0.0))
+    } else {
+        None
+    }
+}
+
+/// Finds the first tile the ray hits, given the same columns of stacked tiles
+/// that were handed to `Renderer::set_tiles`.
+pub fn pick<'a>(ray: &Ray, columns: impl IntoIterator<Item = &'a [Tile]>) -> Option<Hit> {
+    columns
+        .into_iter()
+        .filter_map(|column| {
+            let elevations = Tile::stack_elevations(column).collect::<Vec<f32>>();
+            let first = column.first()?;
+            let top = *elevations.last()?;
+            let bottom = elevations[0] - first.butt_size;
+
+            let t = enter_prism(ray, first.position.to_world(), bottom, top)?;
+            let point = ray.at(t);
+
+            // the highest layer whose bottom lies beneath the point
+            let layer = column
+                .iter()
+                .zip(&elevations)
+                .rposition(|(tile, &elevation)| point.z >= elevation - tile.butt_size - 1e-4)
+                .unwrap_or(0);
+
+            Some((
+                t,
+                Hit {
+                    position: first.position,
+                    layer,
+                    point,
+                },
+            ))
+        })
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(_, hit)| hit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextureId;
+
+    fn tile(col: i32, row: i32, elevation: f32, butt_size: f32) -> Tile {
+        Tile {
+            position: Offset::new(col, row),
+            elevation,
+            butt_size,
+            hat: TextureId(0),
+            butt: TextureId(0),
+            highlight: None,
+        }
+    }
+
+    fn down_at(x: f32, y: f32) -> Ray {
+        Ray {
+            origin: Point3::new(x, y, 10.0),
+            direction: -Vector3::z(),
+        }
+    }
+
+    /// Three tiles, each half a unit tall, the bottom one's top at 0.0.
+    fn stack() -> Vec<Tile> {
+        vec![
+            tile(0, 0, 0.0, 1.0),
+            tile(0, 0, 0.0, 0.5),
+            tile(0, 0, 0.0, 0.5),
+        ]
+    }
+
+    #[test]
+    fn stacked_tiles_rest_on_one_another() {
+        let elevations = Tile::stack_elevations(&stack()).collect::<Vec<_>>();
+        assert_eq!(elevations, vec![0.0, 0.5, 1.0]);
+
+        let raised = vec![
+            tile(0, 0, 2.0, 1.0),
+            tile(0, 0, 2.0, 0.75),
+            tile(0, 0, 2.0, 0.25),
+        ];
+        let elevations = Tile::stack_elevations(&raised).collect::<Vec<_>>();
+        assert_eq!(elevations, vec![2.0, 2.75, 3.0]);
+    }
+
+    #[test]
+    fn hits_the_top_of_a_tile() {
+        let column = vec![tile(0, 0, 1.0, 1.0)];
+        let hit = pick(&down_at(0.1, 0.2), vec![&column[..]]).unwrap();
+        assert_eq!(hit.position, Offset::new(0, 0));
+        assert_eq!(hit.layer, 0);
+        assert!((hit.point - Point3::new(0.1, 0.2, 1.0)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn misses_beside_a_tile() {
+        let column = vec![tile(0, 0, 1.0, 1.0)];
+        assert_eq!(pick(&down_at(0.9, 0.0), vec![&column[..]]), None);
+        assert_eq!(pick(&down_at(5.0, 5.0), vec![&column[..]]), None);
+    }
+
+    #[test]
+    fn misses_a_tile_behind_the_ray() {
+        let column = vec![tile(0, 0, 1.0, 1.0)];
+        let up = Ray {
+            origin: Point3::new(0.0, 0.0, 10.0),
+            direction: Vector3::z(),
+        };
+        assert_eq!(pick(&up, vec![&column[..]]), None);
+    }
+
+    #[test]
+    fn hits_the_top_layer_of_a_stack() {
+        let column = stack();
+        let hit = pick(&down_at(0.0, 0.0), vec![&column[..]]).unwrap();
+        assert_eq!(hit.layer, 2);
+        assert!((hit.point.z - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hits_the_side_of_a_stack() {
+        let column = stack();
+        let from_the_left = |z| Ray {
+            origin: Point3::new(-10.0, 0.0, z),
+            direction: Vector3::x() * 2.0,
+        };
+
+        let hit = pick(&from_the_left(0.25), vec![&column[..]]).unwrap();
+        assert_eq!(hit.layer, 1);
+        assert!((hit.point.x + apothem()).abs() < 1e-4);
+
+        let hit = pick(&from_the_left(-0.5), vec![&column[..]]).unwrap();
+        assert_eq!(hit.layer, 0);
+
+        let hit = pick(&from_the_left(0.75), vec![&column[..]]).unwrap();
+        assert_eq!(hit.layer, 2);
+
+        assert_eq!(pick(&from_the_left(1.5), vec![&column[..]]), None);
+        assert_eq!(pick(&from_the_left(-1.5), vec![&column[..]]), None);
+    }
+
+    #[test]
+    fn hits_the_nearest_column() {
+        let near = vec![tile(0, 0, 0.0, 1.0)];
+        let far = vec![tile(1, 0, 3.0, 4.0)];
+        let ray = Ray {
+            origin: Point3::new(-10.0, 0.0, -0.5),
+            direction: Vector3::x(),
+        };
+
+        let hit = pick(&ray, vec![&far[..], &near[..]]).unwrap();
+        assert_eq!(hit.position, Offset::new(0, 0));
+    }
+}
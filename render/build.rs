@@ -0,0 +1,99 @@
+//! Compiles every shader in `shader/` into SPIR-V ahead of time, so the renderer doesn't need
+//! shaderc at runtime, unless it's hot reloading them.
+//! `shader/<folder>/shader.vert` ends up at `$OUT_DIR/<folder>/shader.vert.spv`, and so on.
+//! `#include "name.glsl"` pulls in `shader/include/name.glsl`, the code shaders share.
+//!
+//! A shader that doesn't compile fails the build. While working on shaders, setting
+//! `HEXA_SHADER_FALLBACK` builds fragment shaders that don't compile as the magenta error
+//! shader instead, and the renderer logs why at startup; release builds never allow that.
+use std::path::{Path, PathBuf};
+
+/// The folder in `shader/` holding code that's included into shaders, rather than shaders.
+const INCLUDE_DIR: &str = "include";
+
+fn main() {
+    let shader_dir = Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("../shader");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-changed={}", shader_dir.display());
+    println!("cargo:rerun-if-env-changed=HEXA_SHADER_FALLBACK");
+
+    let fallback = std::env::var_os("HEXA_SHADER_FALLBACK").is_some();
+    if fallback && std::env::var("PROFILE").map_or(false, |p| p == "release") {
+        panic!("HEXA_SHADER_FALLBACK can't be used for release builds");
+    }
+    // each fragment shader built as the error shader: its label, a newline, and why,
+    // separated by nulls; see `shader::report_build_fallbacks`
+    let mut fallbacks = String::new();
+
+    let mut compiler = shaderc::Compiler::new().expect("couldn't start shaderc");
+    let mut options = shaderc::CompileOptions::new().expect("couldn't start shaderc");
+    let include_dir = shader_dir.join(INCLUDE_DIR);
+    options.set_include_callback(move |name, _, _, _| {
+        let path = include_dir.join(name);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: format!("{}/{}", INCLUDE_DIR, name),
+            content,
+        })
+    });
+    let mut compile = |path: &Path, label: &str| {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let kind = match path.extension().and_then(|e| e.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            _ => return None,
+        };
+        let src = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("couldn't read {}: {}", path.display(), e));
+        Some((
+            kind,
+            compiler
+                .compile_into_spirv(&src, kind, label, "main", Some(&options))
+                .map(|spirv| spirv.as_binary_u8().to_vec()),
+        ))
+    };
+
+    let error_shader = match compile(&shader_dir.join("error/shader.frag"), "error/shader.frag") {
+        Some((_, Ok(spirv))) => spirv,
+        _ => panic!("the error shader must compile"),
+    };
+
+    for folder in std::fs::read_dir(&shader_dir).expect("couldn't read shader directory") {
+        let folder = folder.unwrap().path();
+        if !folder.is_dir() || folder.ends_with(INCLUDE_DIR) {
+            continue;
+        }
+        let folder_name = folder.file_name().unwrap().to_string_lossy().into_owned();
+        std::fs::create_dir_all(out_dir.join(&folder_name)).unwrap();
+
+        for file in std::fs::read_dir(&folder).unwrap() {
+            let path = file.unwrap().path();
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let label = format!("{}/{}", folder_name, file_name);
+
+            let spirv = match compile(&path, &label) {
+                Some((_, Ok(spirv))) => spirv,
+                Some((shaderc::ShaderKind::Fragment, Err(e))) if fallback => {
+                    println!(
+                        "cargo:warning={} doesn't compile, drawing with the error shader instead",
+                        label
+                    );
+                    fallbacks.push_str(&format!("{}\n{}\0", label, e));
+                    error_shader.clone()
+                }
+                Some((_, Err(e))) => panic!("couldn't compile {}:\n{}", label, e),
+                None => continue,
+            };
+
+            let out = out_dir
+                .join(&folder_name)
+                .join(format!("{}.spv", file_name));
+            std::fs::write(&out, spirv)
+                .unwrap_or_else(|e| panic!("couldn't write {}: {}", out.display(), e));
+        }
+    }
+
+    std::fs::write(out_dir.join("fallbacks.txt"), fallbacks).expect("couldn't write fallbacks");
+}
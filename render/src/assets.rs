@@ -0,0 +1,120 @@
+//! Loading the images tiles and sprites are textured with from disk,
+//! and giving each of them a name scenes can refer to them by.
+use hexa::TextureId;
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+/// Where textures are loaded from: `$HEXA_ASSETS` if it's set, otherwise the closest `img`
+/// folder to the executable, otherwise `img` in the working directory.
+pub fn asset_dir() -> PathBuf {
+    std::env::var_os("HEXA_ASSETS")
+        .map(PathBuf::from)
+        .or_else(|| hexa::paths::beside_executable(&["img"]))
+        .unwrap_or_else(|| PathBuf::from("img"))
+}
+
+/// Every `.png` image in a directory, sorted by file name so that ids don't change
+/// from one run to the next unless images are added or removed.
+/// They all end up as layers of one texture array, so they must all be the same size.
+pub struct TextureSet {
+    pub dir: PathBuf,
+    /// The file stem of each image, and the image. A texture's id is its index in here.
+    pub images: Vec<(String, RgbaImage)>,
+}
+impl TextureSet {
+    pub fn load(dir: &Path) -> Result<Self, failure::Error> {
+        let mut paths = std::fs::read_dir(dir)
+            .map_err(|e| failure::format_err!("can't read textures from {}: {}", dir.display(), e))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()?;
+        paths.retain(|p| p.extension().map_or(false, |e| e == "png"));
+        paths.sort();
+
+        let images = paths
+            .iter()
+            .map(|path| {
+                let name = path
+                    .file_stem()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                let image = image::open(path)
+                    .map_err(|e| failure::format_err!("can't load {}: {}", path.display(), e))?
+                    .to_rgba();
+                Ok((name, image))
+            })
+            .collect::<Result<Vec<_>, failure::Error>>()?;
+
+        let (first, first_image) = images
+            .first()
+            .ok_or_else(|| failure::format_err!("there are no .png images in {}", dir.display()))?;
+        for (name, image) in &images {
+            if image.dimensions() != first_image.dimensions() {
+                failure::bail!(
+                    "{name}.png is {:?}, but {first}.png is {:?}; every image in {dir} must be the same size",
+                    image.dimensions(),
+                    first_image.dimensions(),
+                    name = name,
+                    first = first,
+                    dir = dir.display(),
+                );
+            }
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            images,
+        })
+    }
+
+    /// Loads the images in this set's directory again, for when they've changed on disk.
+    /// Scenes hold on to the ids they've looked up, so an image being added, removed or renamed
+    /// would swap textures around under them; that's an error, and needs a restart to pick up.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload(&self) -> Result<Self, failure::Error> {
+        let reloaded = Self::load(&self.dir)?;
+        let names = |set: &Self| {
+            set.images
+                .iter()
+                .map(|(n, _)| n.clone())
+                .collect::<Vec<_>>()
+        };
+        if names(&reloaded) != names(self) {
+            failure::bail!(
+                "images were added to or removed from {}, restart to use them",
+                self.dir.display()
+            );
+        }
+        Ok(reloaded)
+    }
+
+    /// The id of the image with this file stem, if there is one.
+    pub fn id(&self, name: &str) -> Option<TextureId> {
+        self.images
+            .iter()
+            .position(|(n, _)| n == name)
+            .map(|i| TextureId(i as u32))
+    }
+
+    /// The file stem of the image with this id, if there is one.
+    pub fn name(&self, id: TextureId) -> Option<&str> {
+        self.images
+            .get(id.0 as usize)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The textures for tiles, from the `hex` folder of the asset directory,
+/// and those for sprites, from its `sprite` folder.
+pub struct TextureRegistry {
+    pub tiles: TextureSet,
+    pub sprites: TextureSet,
+}
+impl TextureRegistry {
+    pub fn load(asset_dir: &Path) -> Result<Self, failure::Error> {
+        Ok(Self {
+            tiles: TextureSet::load(&asset_dir.join("hex"))?,
+            sprites: TextureSet::load(&asset_dir.join("sprite"))?,
+        })
+    }
+}
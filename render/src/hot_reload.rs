@@ -0,0 +1,110 @@
+//! Watching the shader and image directories while developing, so that changes to them
+//! show up without having to rebuild and restart.
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+/// Where the GLSL that's compiled into the binary lives.
+pub fn shader_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../shader")
+}
+
+/// What changed on disk since the last time we checked.
+#[derive(Debug, Default)]
+pub struct Changes {
+    /// The folders in the shader directory with changed shaders in them, e.g. "hex".
+    /// When code shared through `#include` changes, every folder is in here.
+    pub shaders: HashSet<String>,
+    pub tile_textures: bool,
+    pub sprite_textures: bool,
+}
+
+pub struct HotReload {
+    /// Never used, but dropping it would stop the events.
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    pub shader_dir: PathBuf,
+    asset_dir: PathBuf,
+}
+impl HotReload {
+    pub fn new(asset_dir: &Path) -> Result<Self, failure::Error> {
+        let shader_dir = shader_dir().canonicalize()?;
+        let asset_dir = asset_dir.canonicalize()?;
+
+        let (tx, events) = channel();
+        let mut watcher = notify::watcher(tx, Duration::from_millis(200))?;
+        watcher.watch(&shader_dir, RecursiveMode::Recursive)?;
+        watcher.watch(&asset_dir, RecursiveMode::Recursive)?;
+        log::info!(
+            "watching {} and {} for changes",
+            shader_dir.display(),
+            asset_dir.display()
+        );
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            shader_dir,
+            asset_dir,
+        })
+    }
+
+    /// Everything that's changed since this was last called. Never blocks.
+    pub fn changes(&self) -> Changes {
+        let mut changes = Changes::default();
+
+        for event in self.events.try_iter() {
+            let path = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Remove(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                DebouncedEvent::Error(e, path) => {
+                    log::warn!("error watching {:?} for changes: {}", path, e);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let folder = |root: &Path| {
+                path.strip_prefix(root).ok().and_then(|p| {
+                    p.components()
+                        .next()
+                        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                })
+            };
+            if let Some(folder) = folder(&self.shader_dir) {
+                if folder == "include" {
+                    changes.shaders.extend(self.shader_folders());
+                } else {
+                    changes.shaders.insert(folder);
+                }
+            } else if let Some(folder) = folder(&self.asset_dir) {
+                match folder.as_str() {
+                    "hex" => changes.tile_textures = true,
+                    "sprite" => changes.sprite_textures = true,
+                    _ => {}
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Every folder of shaders in the shader directory.
+    fn shader_folders(&self) -> Vec<String> {
+        std::fs::read_dir(&self.shader_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| name != "include")
+                    .collect()
+            })
+            .unwrap_or_else(|e| {
+                log::warn!("couldn't list {}: {}", self.shader_dir.display(), e);
+                Vec::new()
+            })
+    }
+}
@@ -1,90 +1,199 @@
-use hexa::{iced_wgpu, iced_winit, Camera, Sprite, Tile};
+use hexa::{
+    coord::Offset, iced_wgpu, iced_winit, Camera, Highlight, Lighting, RenderStats, Sky, Sprite,
+    TextureId, Tile,
+};
 use iced_wgpu::{wgpu, Primitive as GuiPrimitive, Renderer as IcedRenderer};
 use iced_winit::{mouse, winit, Debug as IcedDebug, Size};
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+mod assets;
+#[cfg(feature = "hot_reload")]
+mod hot_reload;
 mod multisampled_framebuffer;
+mod offscreen;
 mod pipeline;
 mod rendering_state;
+mod settings;
+pub mod shader;
+mod shadow_map;
 mod texture;
 
+use assets::TextureRegistry;
 use multisampled_framebuffer::MultisampledFramebuffer;
-use pipeline::{FullscreenTrianglePipeline, HexPipeline, QuadPipeline};
+use offscreen::Offscreen;
+use pipeline::{FullscreenTrianglePipeline, HexPipeline, QuadPipeline, SkyPipeline, WaterPipeline};
 pub use rendering_state::RenderingState;
+use settings::supported_sample_count;
+pub use settings::RenderSettings;
+use shadow_map::ShadowMap;
 
 pub struct Config {
-    pub msaa: u32,
+    /// With msaa lowered to what the adapter supports.
+    pub settings: RenderSettings,
     pub camera: Camera,
+    /// Where textures are loaded from, see `assets::asset_dir`.
+    pub assets: std::path::PathBuf,
 }
 
 pub struct Renderer {
     config: Config,
     quad_pipeline: QuadPipeline,
     hex_pipeline: HexPipeline,
+    sky_pipeline: SkyPipeline,
+    water_pipeline: WaterPipeline,
     fullscreen_triangle_pipeline: FullscreenTrianglePipeline,
     framebuffer: wgpu::TextureView,
     depth_texture: texture::Texture,
+    shadow_map: ShadowMap,
+    textures: TextureRegistry,
     rs: RenderingState,
+    /// Where frames go instead of the swap chain when rendering headlessly.
+    offscreen: Option<Offscreen>,
+    /// When the renderer was made, for animating the water.
+    started: std::time::Instant,
+    /// Watches the shader and asset directories, if that was possible.
+    #[cfg(feature = "hot_reload")]
+    hot_reload: Option<hot_reload::HotReload>,
     pub iced_renderer: IcedRenderer,
     pub iced_debug: IcedDebug,
 }
 impl Renderer {
-    pub fn new(window: &Window) -> Self {
+    pub fn new(window: &Window, settings: RenderSettings) -> Result<Self, failure::Error> {
+        Self::from_rendering_state(RenderingState::new(&window, &settings), settings)
+    }
+
+    /// Builds a Renderer that has no window, and draws into an offscreen texture
+    /// of the given size instead. Frames are retrieved with `Renderer::capture`.
+    pub fn headless(
+        width: u32,
+        height: u32,
+        settings: RenderSettings,
+    ) -> Result<Self, failure::Error> {
+        let rs = RenderingState::headless(width, height, &settings)?;
+        let mut renderer = Self::from_rendering_state(rs, settings)?;
+        renderer.offscreen = Some(Offscreen::new(&renderer.rs));
+        Ok(renderer)
+    }
+
+    fn from_rendering_state(
+        mut rs: RenderingState,
+        settings: RenderSettings,
+    ) -> Result<Self, failure::Error> {
         use iced_wgpu::{Backend, Settings};
 
-        let mut rs = RenderingState::new(&window);
+        shader::report_build_fallbacks();
 
         let iced_debug = IcedDebug::new();
         let iced_renderer = IcedRenderer::new(Backend::new(&mut rs.device, Settings::default()));
 
         let config = Config {
             camera: Default::default(),
-            msaa: 16,
+            settings: RenderSettings {
+                msaa: supported_sample_count(settings.msaa, &rs.adapter_info),
+                ..settings
+            },
+            assets: assets::asset_dir(),
         };
+        let textures = TextureRegistry::load(&config.assets)?;
 
-        let multisampled_framebuffer =
-            MultisampledFramebuffer::new(&rs.device, &rs.swap_chain_descriptor, config.msaa);
+        let multisampled_framebuffer = MultisampledFramebuffer::new(
+            &rs.device,
+            &rs.swap_chain_descriptor,
+            config.settings.msaa,
+        );
 
         let depth_texture = texture::Texture::create_depth_texture(
             &rs.device,
-            &rs.swap_chain_descriptor,
-            config.msaa,
+            (
+                rs.swap_chain_descriptor.width,
+                rs.swap_chain_descriptor.height,
+            ),
+            config.settings.msaa,
             "depth_texture",
         );
-
-        let hex_pipeline = HexPipeline::new(&rs, &config.camera, &config);
-        let quad_pipeline = QuadPipeline::new(&rs, &config.camera, &config);
+        let shadow_map = ShadowMap::new(&rs, &config.camera);
+
+        let hex_pipeline =
+            HexPipeline::new(&rs, &config.camera, &config, &textures.tiles, &shadow_map)?;
+        let quad_pipeline =
+            QuadPipeline::new(&rs, &config.camera, &config, &textures.sprites, &shadow_map)?;
+        let sky_pipeline = SkyPipeline::new(&rs, &config.camera, &config)?;
+        let water_pipeline = WaterPipeline::new(&rs, &config.camera, &config, &shadow_map)?;
         let fullscreen_triangle_pipeline =
-            FullscreenTrianglePipeline::new(&rs, multisampled_framebuffer.no_srgb_texture_view);
+            FullscreenTrianglePipeline::new(&rs, multisampled_framebuffer.no_srgb_texture_view)?;
 
-        Self {
+        Ok(Self {
             framebuffer: multisampled_framebuffer.texture_view,
             fullscreen_triangle_pipeline,
             hex_pipeline,
             quad_pipeline,
+            sky_pipeline,
+            water_pipeline,
             depth_texture,
+            shadow_map,
+            textures,
             rs,
+            offscreen: None,
+            started: std::time::Instant::now(),
+            #[cfg(feature = "hot_reload")]
+            hot_reload: hot_reload::HotReload::new(&config.assets)
+                .map_err(|e| log::error!("can't watch for changes to hot reload: {}", e))
+                .ok(),
             config,
             iced_renderer,
             iced_debug,
-        }
+        })
     }
 
     pub fn resize(&mut self, screen: PhysicalSize<u32>, window: &Window) {
         self.rs.resize(screen, &window);
+        self.rebuild_framebuffers();
+    }
+
+    pub fn settings(&self) -> RenderSettings {
+        self.config.settings
+    }
+
+    /// Applies new settings, rebuilding whatever depends on the ones that changed.
+    /// The power preference can't be changed without restarting.
+    pub fn set_settings(&mut self, settings: RenderSettings) {
+        let settings = RenderSettings {
+            msaa: supported_sample_count(settings.msaa, &self.rs.adapter_info),
+            ..settings
+        };
+        let old = std::mem::replace(&mut self.config.settings, settings);
 
+        if settings.power_preference != old.power_preference {
+            log::warn!("the power preference only takes effect when the renderer is created");
+        }
+        if settings.present_mode != old.present_mode {
+            self.rs.swap_chain_descriptor.present_mode = settings.present_mode;
+            self.rs.rebuild_swap_chain();
+        }
+        if settings.msaa != old.msaa {
+            self.rebuild_framebuffers();
+            self.hex_pipeline.rebuild_pipeline(&self.rs, &self.config);
+            self.quad_pipeline.rebuild_pipeline(&self.rs, &self.config);
+            self.sky_pipeline.rebuild_pipeline(&self.rs, &self.config);
+            self.water_pipeline.rebuild_pipeline(&self.rs, &self.config);
+        }
+    }
+
+    /// Builds the depth texture and framebuffers again, for a new size or sample count.
+    fn rebuild_framebuffers(&mut self) {
+        let sc_desc = &self.rs.swap_chain_descriptor;
         self.depth_texture = texture::Texture::create_depth_texture(
             &self.rs.device,
-            &self.rs.swap_chain_descriptor,
-            self.config.msaa,
+            (sc_desc.width, sc_desc.height),
+            self.config.settings.msaa,
             "depth_texture",
         );
 
         let multisampled_framebuffer = MultisampledFramebuffer::new(
             &self.rs.device,
             &self.rs.swap_chain_descriptor,
-            self.config.msaa,
+            self.config.settings.msaa,
         );
         self.fullscreen_triangle_pipeline
             .resize(multisampled_framebuffer.no_srgb_texture_view, &self.rs);
@@ -92,9 +201,14 @@ impl Renderer {
     }
 
     pub fn render(&mut self, window: &Window, gui: &(GuiPrimitive, mouse::Interaction)) {
+        #[cfg(feature = "hot_reload")]
+        self.hot_reload();
+
         let frame = self
             .rs
             .swap_chain
+            .as_mut()
+            .expect("headless renderers can't render to a window, use capture instead")
             .get_next_texture()
             .expect("Timeout getting texture");
 
@@ -103,6 +217,143 @@ impl Renderer {
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
+        let seconds = self.started.elapsed().as_secs_f32();
+        self.render_scene(&mut encoder, &frame.view, seconds);
+
+        // And update the mouse cursor
+        window.set_cursor_icon(iced_winit::conversion::mouse_interaction(
+            self.iced_renderer.backend_mut().draw(
+                &mut self.rs.device,
+                &mut encoder,
+                &frame.view,
+                &self.rs.viewport,
+                gui,
+                &self.iced_debug.overlay(),
+            ),
+        ));
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
+    /// Renders the tiles and sprites into the offscreen texture of a headless Renderer,
+    /// and returns the resulting frame. The GUI is not drawn.
+    /// Animations like the water's waves are always drawn as they are at the start,
+    /// so capturing the same scene twice gives the same frame.
+    pub fn capture(&mut self) -> Result<image::RgbaImage, failure::Error> {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        let offscreen = self
+            .offscreen
+            .take()
+            .ok_or_else(|| failure::err_msg("only headless renderers can capture frames"))?;
+        self.render_scene(&mut encoder, &offscreen.view, 0.0);
+        let frame = offscreen.read(encoder, &self.rs);
+        self.offscreen = Some(offscreen);
+
+        frame
+    }
+
+    /// Rebuilds whichever pipelines and texture arrays have changed on disk.
+    /// Anything that fails to reload is logged, and the last good version is kept.
+    #[cfg(feature = "hot_reload")]
+    fn hot_reload(&mut self) {
+        let hot_reload = match &self.hot_reload {
+            Some(hot_reload) => hot_reload,
+            None => return,
+        };
+        let changes = hot_reload.changes();
+
+        for folder in &changes.shaders {
+            let dir = hot_reload.shader_dir.join(folder);
+            let sources = std::fs::read_to_string(dir.join("shader.vert"))
+                .and_then(|vs| std::fs::read_to_string(dir.join("shader.frag")).map(|fs| (vs, fs)));
+            let (vs, fs) = match sources {
+                Ok(sources) => sources,
+                Err(e) => {
+                    log::error!("couldn't read {} shaders: {}", folder, e);
+                    continue;
+                }
+            };
+
+            let reloaded = match folder.as_str() {
+                "hex" => self
+                    .hex_pipeline
+                    .reload_shaders(&self.rs, &self.config, &vs, &fs),
+                "quad" => self
+                    .quad_pipeline
+                    .reload_shaders(&self.rs, &self.config, &vs, &fs),
+                "sky" => self
+                    .sky_pipeline
+                    .reload_shaders(&self.rs, &self.config, &vs, &fs),
+                "water" => self
+                    .water_pipeline
+                    .reload_shaders(&self.rs, &self.config, &vs, &fs),
+                "hex_shadow" => self.hex_pipeline.reload_shadow_shaders(&self.rs, &vs, &fs),
+                "quad_shadow" => self.quad_pipeline.reload_shadow_shaders(&self.rs, &vs, &fs),
+                "no_srgb" => self
+                    .fullscreen_triangle_pipeline
+                    .reload_shaders(&self.rs, &vs, &fs),
+                _ => continue,
+            };
+            match reloaded {
+                Ok(()) => log::info!("reloaded {} shaders", folder),
+                Err(e) => log::error!("keeping the old {} shaders: {}", folder, e),
+            }
+        }
+
+        if changes.tile_textures {
+            let hex_pipeline = &mut self.hex_pipeline;
+            let rs = &self.rs;
+            match self.textures.tiles.reload().and_then(|tiles| {
+                hex_pipeline.set_textures(rs, &tiles)?;
+                Ok(tiles)
+            }) {
+                Ok(tiles) => {
+                    log::info!("reloaded tile textures");
+                    self.textures.tiles = tiles;
+                }
+                Err(e) => log::error!("keeping the old tile textures: {}", e),
+            }
+        }
+        if changes.sprite_textures {
+            let quad_pipeline = &mut self.quad_pipeline;
+            let rs = &self.rs;
+            match self.textures.sprites.reload().and_then(|sprites| {
+                quad_pipeline.set_textures(rs, &sprites)?;
+                Ok(sprites)
+            }) {
+                Ok(sprites) => {
+                    log::info!("reloaded sprite textures");
+                    self.textures.sprites = sprites;
+                }
+                Err(e) => log::error!("keeping the old sprite textures: {}", e),
+            }
+        }
+    }
+
+    /// Draws the scene as it is this many seconds in.
+    fn render_scene(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        frame_view: &wgpu::TextureView,
+        seconds: f32,
+    ) {
+        self.water_pipeline.set_time(encoder, &self.rs, seconds);
+        self.hex_pipeline.cull(encoder, &self.rs);
+        self.quad_pipeline.cull(encoder, &self.rs);
+
+        {
+            let mut render_pass = self.shadow_map.begin_render_pass(encoder);
+
+            self.hex_pipeline
+                .render_shadow(&mut render_pass, &self.shadow_map);
+            self.quad_pipeline
+                .render_shadow(&mut render_pass, &self.shadow_map);
+        }
+
         {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 color_attachments: &[{
@@ -111,17 +362,12 @@ impl Renderer {
                     let base: ColorPass = ColorPass {
                         load_op: wgpu::LoadOp::Clear,
                         store_op: wgpu::StoreOp::Store,
-                        clear_color: wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        },
+                        clear_color: self.config.settings.background,
                         attachment: &self.fullscreen_triangle_pipeline.no_srgb_framebuffer,
                         resolve_target: None,
                     };
 
-                    match self.config.msaa {
+                    match self.config.settings.msaa {
                         1 => base,
                         _ => ColorPass {
                             attachment: &self.framebuffer,
@@ -143,26 +389,16 @@ impl Renderer {
                 }),
             });
 
-            self.hex_pipeline.render(&mut render_pass);
-            self.quad_pipeline.render(&mut render_pass);
+            self.sky_pipeline.render(&mut render_pass);
+            self.hex_pipeline.render(&mut render_pass, &self.shadow_map);
+            self.quad_pipeline
+                .render(&mut render_pass, &self.shadow_map);
+            self.water_pipeline
+                .render(&mut render_pass, &self.shadow_map);
         }
 
         self.fullscreen_triangle_pipeline
-            .render(&mut encoder, &frame.view);
-
-        // And update the mouse cursor
-        window.set_cursor_icon(iced_winit::conversion::mouse_interaction(
-            self.iced_renderer.backend_mut().draw(
-                &mut self.rs.device,
-                &mut encoder,
-                &frame.view,
-                &self.rs.viewport,
-                gui,
-                &self.iced_debug.overlay(),
-            ),
-        ));
-
-        self.rs.queue.submit(&[encoder.finish()]);
+            .render(encoder, frame_view);
     }
 }
 impl hexa::Renderer for Renderer {
@@ -181,6 +417,30 @@ impl hexa::Renderer for Renderer {
         self.rs.queue.submit(&[encoder.finish()]);
     }
 
+    fn update_tiles(&mut self, columns: Vec<(Offset, Vec<Tile>)>) {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.hex_pipeline
+            .update_tiles(&mut encoder, &self.rs, columns);
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
+    fn set_highlights(&mut self, highlights: Vec<(Offset, Highlight)>) {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.hex_pipeline
+            .set_highlights(&mut encoder, &self.rs, highlights);
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
     fn set_sprites(&mut self, sprites: Vec<Sprite>) {
         let mut encoder = self
             .rs
@@ -193,6 +453,44 @@ impl hexa::Renderer for Renderer {
         self.rs.queue.submit(&[encoder.finish()]);
     }
 
+    fn set_lighting(&mut self, lighting: &Lighting) {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.shadow_map
+            .set_lighting(&mut encoder, &self.rs, lighting);
+        let light_view_proj = self.shadow_map.light_view_proj();
+        self.hex_pipeline.set_light_view_proj(light_view_proj);
+        self.quad_pipeline.set_light_view_proj(light_view_proj);
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
+    fn set_sky(&mut self, sky: &Sky) {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.sky_pipeline.set_sky(&mut encoder, &self.rs, sky);
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
+    fn set_sea_level(&mut self, sea_level: Option<f32>) {
+        let mut encoder = self
+            .rs
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        self.water_pipeline
+            .set_sea_level(&mut encoder, &self.rs, sea_level);
+
+        self.rs.queue.submit(&[encoder.finish()]);
+    }
+
     fn set_camera(&mut self, camera: &Camera) {
         let mut encoder = self
             .rs
@@ -202,33 +500,45 @@ impl hexa::Renderer for Renderer {
         self.hex_pipeline.set_camera(&mut encoder, &self.rs, camera);
         self.quad_pipeline
             .set_camera(&mut encoder, &self.rs, camera);
+        self.sky_pipeline.set_camera(&mut encoder, &self.rs, camera);
+        self.water_pipeline
+            .set_camera(&mut encoder, &self.rs, camera);
+        self.shadow_map.set_camera(&mut encoder, &self.rs, camera);
+        let light_view_proj = self.shadow_map.light_view_proj();
+        self.hex_pipeline.set_light_view_proj(light_view_proj);
+        self.quad_pipeline.set_light_view_proj(light_view_proj);
 
         self.rs.queue.submit(&[encoder.finish()]);
     }
 
+    fn render_stats(&self) -> RenderStats {
+        let (tiles_drawn, tiles_culled) = self.hex_pipeline.stats();
+        let (sprites_drawn, sprites_culled) = self.quad_pipeline.stats();
+        RenderStats {
+            tiles_drawn,
+            tiles_culled,
+            sprites_drawn,
+            sprites_culled,
+        }
+    }
+
+    fn tile_texture(&self, name: &str) -> Option<TextureId> {
+        self.textures.tiles.id(name)
+    }
+
+    fn sprite_texture(&self, name: &str) -> Option<TextureId> {
+        self.textures.sprites.id(name)
+    }
+
+    fn tile_texture_name(&self, id: TextureId) -> Option<&str> {
+        self.textures.tiles.name(id)
+    }
+
+    fn sprite_texture_name(&self, id: TextureId) -> Option<&str> {
+        self.textures.sprites.name(id)
+    }
+
     fn iced_mut(&mut self) -> (&mut IcedRenderer, &mut IcedDebug) {
         (&mut self.iced_renderer, &mut self.iced_debug)
     }
 }
-
-fn compile_shaders(
-    (vs_src, vs_lbl): (&str, &str),
-    (fs_src, fs_lbl): (&str, &str),
-    rs: &RenderingState,
-) -> (wgpu::ShaderModule, wgpu::ShaderModule) {
-    let mut compiler = shaderc::Compiler::new().unwrap();
-    let vs_spirv = compiler
-        .compile_into_spirv(vs_src, shaderc::ShaderKind::Vertex, vs_lbl, "main", None)
-        .unwrap_or_else(|e| panic!("couldn't compile vertex shader: {}", e));
-    let fs_spirv = compiler
-        .compile_into_spirv(fs_src, shaderc::ShaderKind::Fragment, fs_lbl, "main", None)
-        .unwrap_or_else(|e| panic!("couldn't compile fragment shader: {}", e));
-
-    let vs_data = wgpu::read_spirv(std::io::Cursor::new(vs_spirv.as_binary_u8())).unwrap();
-    let fs_data = wgpu::read_spirv(std::io::Cursor::new(fs_spirv.as_binary_u8())).unwrap();
-
-    (
-        rs.device.create_shader_module(&vs_data),
-        rs.device.create_shader_module(&fs_data),
-    )
-}
@@ -0,0 +1,95 @@
+use crate::RenderingState;
+use hexa::iced_wgpu::wgpu;
+
+/// wgpu requires each row of a texture-to-buffer copy to be aligned to this many bytes.
+const BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// A texture that stands in for the swap chain when rendering headlessly,
+/// along with what's needed to read a frame back out of it.
+pub struct Offscreen {
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+}
+impl Offscreen {
+    pub fn new(rs: &RenderingState) -> Self {
+        let sc_desc = &rs.swap_chain_descriptor;
+        let texture = rs.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen frame"),
+            size: wgpu::Extent3d {
+                width: sc_desc.width,
+                height: sc_desc.height,
+                depth: 1,
+            },
+            array_layer_count: 1,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: sc_desc.format,
+            usage: sc_desc.usage,
+        });
+
+        Self {
+            view: texture.create_default_view(),
+            texture,
+            width: sc_desc.width,
+            height: sc_desc.height,
+        }
+    }
+
+    /// Copies the frame into a buffer, waits for the GPU to finish, and returns it as an RGBA image.
+    pub fn read(
+        &self,
+        mut encoder: wgpu::CommandEncoder,
+        rs: &RenderingState,
+    ) -> Result<image::RgbaImage, failure::Error> {
+        let unpadded_bytes_per_row = 4 * self.width;
+        let padding = (BYTES_PER_ROW_ALIGNMENT - unpadded_bytes_per_row % BYTES_PER_ROW_ALIGNMENT)
+            % BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = unpadded_bytes_per_row + padding;
+        let buffer_size = (bytes_per_row * self.height) as wgpu::BufferAddress;
+
+        let readback_buffer = rs.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback buffer"),
+            size: buffer_size,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+        });
+
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.texture,
+                mip_level: 0,
+                array_layer: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &readback_buffer,
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: self.height,
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth: 1,
+            },
+        );
+        rs.queue.submit(&[encoder.finish()]);
+
+        let mapping = readback_buffer.map_read(0, buffer_size);
+        rs.device.poll(wgpu::Maintain::Wait);
+        let mapping = futures::executor::block_on(mapping)
+            .map_err(|_| failure::err_msg("couldn't map offscreen readback buffer"))?;
+
+        let pixels = mapping
+            .as_slice()
+            .chunks(bytes_per_row as usize)
+            .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+            .copied()
+            .collect::<Vec<u8>>();
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels)
+            .ok_or_else(|| failure::err_msg("offscreen frame had an unexpected size"))
+    }
+}
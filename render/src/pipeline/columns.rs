@@ -0,0 +1,278 @@
+use hexa::coord::Offset;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Where a column's instances live in `Columns::instances`.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    start: usize,
+    /// How many instances the column currently has.
+    len: usize,
+    /// How many instances the column can grow to before it has to move.
+    capacity: usize,
+}
+impl Slot {
+    fn range(&self) -> Range<usize> {
+        self.start..self.start + self.capacity
+    }
+}
+
+/// Keeps track of which instances belong to which column of tiles, so that a column can be
+/// replaced without disturbing the instances of any other column. Each operation reports which
+/// instances it touched, so only those need to be uploaded to the GPU.
+///
+/// Instances that don't belong to any tile are zeroed, which the shader turns into degenerate
+/// triangles that never reach the screen.
+pub struct Columns<T> {
+    pub instances: Vec<T>,
+    /// Which column each of the `instances` belongs to; meaningless for zeroed instances.
+    pub positions: Vec<Offset>,
+    slots: HashMap<Offset, Slot>,
+    /// Stretches of zeroed instances that no column is using.
+    free: Vec<Range<usize>>,
+}
+impl<T: Copy + bytemuck::Zeroable> Columns<T> {
+    pub fn new() -> Self {
+        Self {
+            instances: Vec::new(),
+            positions: Vec::new(),
+            slots: HashMap::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.instances.clear();
+        self.positions.clear();
+        self.slots.clear();
+        self.free.clear();
+    }
+
+    /// The instances of the column at this position.
+    pub fn range(&self, position: &Offset) -> Option<Range<usize>> {
+        self.slots.get(position).map(|s| s.start..s.start + s.len)
+    }
+
+    /// How many instances don't belong to any tile.
+    fn unused(&self) -> usize {
+        self.free.iter().map(|r| r.len()).sum::<usize>()
+            + self
+                .slots
+                .values()
+                .map(|s| s.capacity - s.len)
+                .sum::<usize>()
+    }
+
+    /// Whether enough instances are going to waste that it's worth calling `compact`.
+    pub fn needs_compacting(&self) -> bool {
+        self.len() > 1024 && self.unused() > self.len() / 2
+    }
+
+    /// Packs every column tightly together, which moves nearly everything,
+    /// so all of the instances need to be uploaded again afterwards.
+    pub fn compact(&mut self) {
+        let mut slots = self.slots.drain().collect::<Vec<_>>();
+        slots.sort_by_key(|(_, slot)| slot.start);
+
+        let (mut instances, mut positions) = (Vec::new(), Vec::new());
+        for (position, slot) in slots {
+            let start = instances.len();
+            instances.extend_from_slice(&self.instances[slot.start..slot.start + slot.len]);
+            positions.resize(instances.len(), position);
+            self.slots.insert(
+                position,
+                Slot {
+                    start,
+                    len: slot.len,
+                    capacity: slot.len,
+                },
+            );
+        }
+
+        self.instances = instances;
+        self.positions = positions;
+        self.free.clear();
+    }
+
+    fn zero(&mut self, range: Range<usize>) {
+        for instance in &mut self.instances[range] {
+            *instance = T::zeroed();
+        }
+    }
+
+    /// Finds room for `len` instances, reusing a free stretch if one is big enough.
+    fn allocate(&mut self, len: usize) -> usize {
+        if let Some(i) = self.free.iter().position(|r| r.len() >= len) {
+            let range = self.free.swap_remove(i);
+            if range.len() > len {
+                self.free.push(range.start + len..range.end);
+            }
+            return range.start;
+        }
+
+        let start = self.instances.len();
+        self.instances.resize(start + len, T::zeroed());
+        self.positions.resize(start + len, Offset::default());
+        start
+    }
+
+    /// Replaces the instances of the column at `position`; no instances removes the column.
+    /// Returns the range of instances that changed.
+    pub fn set(&mut self, position: Offset, column: Vec<T>) -> Range<usize> {
+        let old = self.slots.remove(&position);
+        let len = column.len();
+
+        let slot = match old {
+            // it still fits where it was
+            Some(slot) if len > 0 && len <= slot.capacity => Slot { len, ..slot },
+            _ => {
+                if let Some(old) = old {
+                    self.zero(old.range());
+                    self.free.push(old.range());
+                }
+                if len == 0 {
+                    return old.map_or(0..0, |s| s.range());
+                }
+
+                Slot {
+                    start: self.allocate(len),
+                    len,
+                    capacity: len,
+                }
+            }
+        };
+
+        let range = slot.range();
+        self.zero(range.clone());
+        self.instances[slot.start..slot.start + len].copy_from_slice(&column);
+        for p in &mut self.positions[slot.start..slot.start + len] {
+            *p = position;
+        }
+        self.slots.insert(position, slot);
+
+        match old {
+            Some(old) => union(old.range(), range),
+            None => range,
+        }
+    }
+}
+
+/// The smallest range covering both ranges.
+pub fn union(a: Range<usize>, b: Range<usize>) -> Range<usize> {
+    if a.start == a.end {
+        b
+    } else if b.start == b.end {
+        a
+    } else {
+        a.start.min(b.start)..a.end.max(b.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(col: i32) -> Offset {
+        Offset::new(col, 0)
+    }
+
+    #[test]
+    fn new_columns_are_appended() {
+        let mut columns = Columns::<u32>::new();
+        assert_eq!(columns.set(at(0), vec![1, 2]), 0..2);
+        assert_eq!(columns.set(at(1), vec![3, 4, 5]), 2..5);
+
+        assert_eq!(columns.instances, vec![1, 2, 3, 4, 5]);
+        assert_eq!(columns.positions, vec![at(0), at(0), at(1), at(1), at(1)]);
+        assert_eq!(columns.range(&at(1)), Some(2..5));
+        assert_eq!(columns.range(&at(2)), None);
+    }
+
+    #[test]
+    fn shrinking_stays_in_place() {
+        let mut columns = Columns::<u32>::new();
+        columns.set(at(0), vec![1, 2, 3]);
+        columns.set(at(1), vec![4]);
+
+        assert_eq!(columns.set(at(0), vec![9]), 0..3);
+        assert_eq!(columns.instances, vec![9, 0, 0, 4]);
+        assert_eq!(columns.range(&at(0)), Some(0..1));
+
+        // and can grow back into the room it left
+        assert_eq!(columns.set(at(0), vec![7, 8]), 0..3);
+        assert_eq!(columns.instances, vec![7, 8, 0, 4]);
+        assert_eq!(columns.len(), 4);
+    }
+
+    #[test]
+    fn outgrowing_a_slot_moves_the_column() {
+        let mut columns = Columns::<u32>::new();
+        columns.set(at(0), vec![1]);
+        columns.set(at(1), vec![2]);
+
+        // the dirty range covers both where it was and where it went
+        assert_eq!(columns.set(at(0), vec![5, 6]), 0..4);
+        assert_eq!(columns.instances, vec![0, 2, 5, 6]);
+        assert_eq!(columns.range(&at(0)), Some(2..4));
+        assert_eq!(columns.positions[2..4], [at(0), at(0)]);
+    }
+
+    #[test]
+    fn removed_columns_are_zeroed_and_reused() {
+        let mut columns = Columns::<u32>::new();
+        columns.set(at(0), vec![1, 2, 3]);
+        columns.set(at(1), vec![4]);
+
+        assert_eq!(columns.set(at(0), Vec::new()), 0..3);
+        assert_eq!(columns.instances, vec![0, 0, 0, 4]);
+        assert_eq!(columns.range(&at(0)), None);
+
+        // removing what isn't there touches nothing
+        assert_eq!(columns.set(at(5), Vec::new()), 0..0);
+
+        // a smaller column takes the start of the free stretch, leaving the rest free
+        assert_eq!(columns.set(at(2), vec![5, 6]), 0..2);
+        assert_eq!(columns.set(at(3), vec![7]), 2..3);
+        assert_eq!(columns.instances, vec![5, 6, 7, 4]);
+        assert_eq!(columns.positions, vec![at(2), at(2), at(3), at(1)]);
+    }
+
+    #[test]
+    fn compacting_packs_columns_together() {
+        let mut columns = Columns::<u32>::new();
+        for col in 0..600 {
+            columns.set(at(col), vec![col as u32 + 1, col as u32 + 1]);
+        }
+        assert!(!columns.needs_compacting());
+
+        for col in (0..600).filter(|col| col % 3 != 0) {
+            columns.set(at(col), Vec::new());
+        }
+        assert!(columns.needs_compacting());
+
+        columns.compact();
+        assert!(!columns.needs_compacting());
+        assert_eq!(columns.len(), 400);
+        assert!(columns.instances.iter().all(|&i| i != 0));
+        for col in (0..600).step_by(3) {
+            let range = columns.range(&at(col)).unwrap();
+            assert_eq!(columns.instances[range.clone()], [col as u32 + 1; 2]);
+            assert!(columns.positions[range].iter().all(|&p| p == at(col)));
+        }
+        // the order they were in is kept
+        assert_eq!(columns.range(&at(3)), Some(2..4));
+    }
+
+    #[test]
+    fn union_covers_both_ranges() {
+        assert_eq!(union(0..2, 5..7), 0..7);
+        assert_eq!(union(5..7, 1..3), 1..7);
+        assert_eq!(union(2..2, 5..7), 5..7);
+        assert_eq!(union(5..7, 0..0), 5..7);
+        assert_eq!(union(1..4, 2..3), 1..4);
+    }
+}
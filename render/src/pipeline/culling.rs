@@ -0,0 +1,91 @@
+use super::InstanceBuffer;
+use crate::RenderingState;
+use hexa::{frustum::Frustum, iced_wgpu::wgpu};
+use nalgebra::{Matrix4, Point3};
+
+/// Decides which instances the camera can see, and uploads their indexes
+/// so the vertex shader can look the visible instances up by `gl_InstanceIndex`.
+/// Culling only happens again when the camera moves or the instances change.
+pub struct Culling {
+    pub visible: InstanceBuffer,
+    view_proj: Matrix4<f32>,
+    dirty: bool,
+    /// How many instances were visible, last time we checked.
+    pub drawn: usize,
+    /// How many instances weren't.
+    pub culled: usize,
+}
+impl Culling {
+    pub fn new(rs: &RenderingState, label: &'static str) -> Self {
+        Self {
+            visible: InstanceBuffer::new(rs, label, std::mem::size_of::<u32>()),
+            view_proj: Matrix4::identity(),
+            dirty: true,
+            drawn: 0,
+            culled: 0,
+        }
+    }
+
+    pub fn set_view_proj(&mut self, view_proj: Matrix4<f32>) {
+        if view_proj != self.view_proj {
+            self.view_proj = view_proj;
+            self.dirty = true;
+        }
+    }
+
+    /// Culling needs to happen again because the instances changed.
+    pub fn instances_changed(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Uploads the indexes of every instance whose bounding sphere intersects the frustum.
+    /// Instances that aren't tiles or sprites at all should be `None`, and aren't counted.
+    /// Returns whether the buffer of visible indexes was reallocated.
+    pub fn cull(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        bounds: impl Iterator<Item = Option<(Point3<f32>, f32)>>,
+    ) -> bool {
+        let frustum = Frustum::from_matrix(&self.view_proj);
+
+        let mut culled = 0;
+        let visible = bounds
+            .enumerate()
+            .filter_map(|(i, bounds)| {
+                let (center, radius) = bounds?;
+                if frustum.intersects_sphere(&center, radius) {
+                    Some(i as u32)
+                } else {
+                    culled += 1;
+                    None
+                }
+            })
+            .collect::<Vec<u32>>();
+        let visible = &visible[..visible.len().min(self.visible.max_instances())];
+        self.drawn = visible.len();
+        self.culled = culled;
+        self.dirty = false;
+
+        let reallocated = self.visible.reserve(rs, visible.len());
+        if !visible.is_empty() {
+            let staging_buffer = rs.device.create_buffer_with_data(
+                bytemuck::cast_slice(visible),
+                wgpu::BufferUsage::COPY_SRC,
+            );
+            encoder.copy_buffer_to_buffer(
+                &staging_buffer,
+                0,
+                &self.visible.buffer,
+                0,
+                (visible.len() * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            );
+        }
+
+        reallocated
+    }
+}
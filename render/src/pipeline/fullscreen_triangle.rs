@@ -1,4 +1,5 @@
-use crate::{compile_shaders, RenderingState};
+use crate::shader::load_shaders;
+use crate::RenderingState;
 use hexa::iced_wgpu::wgpu;
 
 fn diffuse_bind_group(
@@ -23,7 +24,58 @@ fn diffuse_bind_group(
     })
 }
 
+const VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/no_srgb/shader.vert.spv")),
+    "no_srgb/shader.vert",
+);
+const FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/no_srgb/shader.frag.spv")),
+    "no_srgb/shader.frag",
+);
+
+fn render_pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): (wgpu::ShaderModule, wgpu::ShaderModule),
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: rs.swap_chain_descriptor.format,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[],
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
+
 pub struct FullscreenTriangle {
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
     pub no_srgb_framebuffer: wgpu::TextureView,
     diffuse_sampler: wgpu::Sampler,
@@ -31,18 +83,11 @@ pub struct FullscreenTriangle {
     diffuse_bind_group: wgpu::BindGroup,
 }
 impl FullscreenTriangle {
-    pub fn new(rs: &RenderingState, framebuffer: wgpu::TextureView) -> Self {
-        let (vs_module, fs_module) = compile_shaders(
-            (
-                include_str!("../../../shader/no_srgb/shader.vert"),
-                "no_srgb/shader.vert",
-            ),
-            (
-                include_str!("../../../shader/no_srgb/shader.frag"),
-                "no_srgb/shader.frag",
-            ),
-            rs,
-        );
+    pub fn new(
+        rs: &RenderingState,
+        framebuffer: wgpu::TextureView,
+    ) -> Result<Self, failure::Error> {
+        let shaders = load_shaders(VERTEX_SHADER, FRAGMENT_SHADER, rs)?;
 
         let diffuse_sampler = rs.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -91,49 +136,34 @@ impl FullscreenTriangle {
                     bind_group_layouts: &[&diffuse_bind_group_layout],
                 });
 
-        let render_pipeline = rs
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &render_pipeline_layout,
-                vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vs_module,
-                    entry_point: "main",
-                },
-                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &fs_module,
-                    entry_point: "main",
-                }),
-                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                    front_face: wgpu::FrontFace::Ccw,
-                    cull_mode: wgpu::CullMode::Back,
-                    depth_bias: 0,
-                    depth_bias_slope_scale: 0.0,
-                    depth_bias_clamp: 0.0,
-                }),
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-                color_states: &[wgpu::ColorStateDescriptor {
-                    format: rs.swap_chain_descriptor.format,
-                    color_blend: wgpu::BlendDescriptor::REPLACE,
-                    alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-                depth_stencil_state: None,
-                vertex_state: wgpu::VertexStateDescriptor {
-                    index_format: wgpu::IndexFormat::Uint16,
-                    vertex_buffers: &[],
-                },
-                sample_count: 1,
-                sample_mask: !0,
-                alpha_to_coverage_enabled: false,
-            });
+        let render_pipeline = render_pipeline(rs, &render_pipeline_layout, shaders);
 
-        Self {
+        Ok(Self {
+            render_pipeline_layout,
             render_pipeline,
             no_srgb_framebuffer: framebuffer,
             diffuse_sampler,
             diffuse_bind_group_layout,
             diffuse_bind_group,
-        }
+        })
+    }
+
+    /// Recompiles the shaders from these sources and rebuilds the render pipeline with them,
+    /// keeping the old pipeline if they don't compile.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shaders(
+        &mut self,
+        rs: &RenderingState,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, VERTEX_SHADER.1),
+            (fs_src, FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.render_pipeline = render_pipeline(rs, &self.render_pipeline_layout, shaders);
+        Ok(())
     }
 
     pub fn resize(&mut self, framebuffer: wgpu::TextureView, rs: &RenderingState) {
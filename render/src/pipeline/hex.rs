@@ -1,12 +1,17 @@
-use crate::Config;
-use crate::{compile_shaders, texture, RenderingState, Tile};
-use hexa::{iced_wgpu::wgpu, Camera};
+use super::{columns, instance_buffer, Columns, Culling, InstanceBuffer};
+use crate::shader::load_shaders;
+use crate::{assets::TextureSet, shadow_map, Config, ShadowMap};
+use crate::{texture, RenderingState, Tile};
+use hexa::{coord::Offset, iced_wgpu::wgpu, iced_winit::Color, Camera, Highlight};
+use std::collections::HashMap;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct Vertex {
     position: [f32; 3],
     tex_coords: [f32; 2],
+    /// Straight up on the hat, straight out from the middle of the tile on the butt.
+    normal: [f32; 3],
     image: u16,
 }
 unsafe impl bytemuck::Pod for Vertex {}
@@ -31,6 +36,12 @@ impl Vertex {
                 wgpu::VertexAttributeDescriptor {
                     offset: (mem::size_of::<[f32; 3]>() + mem::size_of::<[f32; 2]>())
                         as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float3,
+                },
+                wgpu::VertexAttributeDescriptor {
+                    offset: (mem::size_of::<[f32; 3]>() * 2 + mem::size_of::<[f32; 2]>())
+                        as wgpu::BufferAddress,
                     shader_location: 2,
                     format: wgpu::VertexFormat::Uint,
                 },
@@ -42,26 +53,26 @@ impl Vertex {
 //main.rs
 #[rustfmt::skip]
 const VERTICES: &[Vertex] = &[
-    Vertex { position: [ 0.000000, -0.000000,  0.0], tex_coords: [0.500000, 0.500000], image: 0 },
-    Vertex { position: [ 0.866025, -0.500000,  0.0], tex_coords: [0.933012, 0.250000], image: 0 },
-    Vertex { position: [ 0.000000, -1.000000,  0.0], tex_coords: [0.500000, 0.000000], image: 0 },
-    Vertex { position: [-0.866025, -0.500000,  0.0], tex_coords: [0.066988, 0.250000], image: 0 },
-    Vertex { position: [-0.866025,  0.500000,  0.0], tex_coords: [0.066988, 0.750000], image: 0 },
-    Vertex { position: [-0.000000,  1.000000,  0.0], tex_coords: [0.500000, 1.000000], image: 0 },
-    Vertex { position: [ 0.866025,  0.500000,  0.0], tex_coords: [0.933012, 0.750000], image: 0 },
+    Vertex { position: [ 0.000000, -0.000000,  0.0], tex_coords: [0.500000, 0.500000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [ 0.866025, -0.500000,  0.0], tex_coords: [0.933012, 0.250000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [ 0.000000, -1.000000,  0.0], tex_coords: [0.500000, 0.000000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [-0.866025, -0.500000,  0.0], tex_coords: [0.066988, 0.250000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [-0.866025,  0.500000,  0.0], tex_coords: [0.066988, 0.750000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [-0.000000,  1.000000,  0.0], tex_coords: [0.500000, 1.000000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
+    Vertex { position: [ 0.866025,  0.500000,  0.0], tex_coords: [0.933012, 0.750000], normal: [ 0.000000,  0.000000, 1.0], image: 0 },
     // bottom verts
-    Vertex { position: [ 0.866025, -0.500000,  0.00], tex_coords: [0.000000, 0.000000], image: 1 },
-    Vertex { position: [ 0.000000, -1.000000,  0.00], tex_coords: [1.000000, 0.000000], image: 1 },
-    Vertex { position: [-0.866025, -0.500000,  0.00], tex_coords: [0.000000, 0.000000], image: 1 },
-    Vertex { position: [-0.866025,  0.500000,  0.00], tex_coords: [1.000000, 0.000000], image: 1 },
-    Vertex { position: [-0.000000,  1.000000,  0.00], tex_coords: [0.000000, 0.000000], image: 1 },
-    Vertex { position: [ 0.866025,  0.500000,  0.00], tex_coords: [1.000000, 0.000000], image: 1 },
-    Vertex { position: [ 0.866025, -0.500000, -1.00], tex_coords: [0.000000, 1.000000], image: 1 },
-    Vertex { position: [ 0.000000, -1.000000, -1.00], tex_coords: [1.000000, 1.000000], image: 1 },
-    Vertex { position: [-0.866025, -0.500000, -1.00], tex_coords: [0.000000, 1.000000], image: 1 },
-    Vertex { position: [-0.866025,  0.500000, -1.00], tex_coords: [1.000000, 1.000000], image: 1 },
-    Vertex { position: [-0.000000,  1.000000, -1.00], tex_coords: [0.000000, 1.000000], image: 1 },
-    Vertex { position: [ 0.866025,  0.500000, -1.00], tex_coords: [1.000000, 1.000000], image: 1 },
+    Vertex { position: [ 0.866025, -0.500000,  0.00], tex_coords: [0.000000, 0.000000], normal: [ 0.866025, -0.500000, 0.0], image: 1 },
+    Vertex { position: [ 0.000000, -1.000000,  0.00], tex_coords: [1.000000, 0.000000], normal: [ 0.000000, -1.000000, 0.0], image: 1 },
+    Vertex { position: [-0.866025, -0.500000,  0.00], tex_coords: [0.000000, 0.000000], normal: [-0.866025, -0.500000, 0.0], image: 1 },
+    Vertex { position: [-0.866025,  0.500000,  0.00], tex_coords: [1.000000, 0.000000], normal: [-0.866025,  0.500000, 0.0], image: 1 },
+    Vertex { position: [-0.000000,  1.000000,  0.00], tex_coords: [0.000000, 0.000000], normal: [ 0.000000,  1.000000, 0.0], image: 1 },
+    Vertex { position: [ 0.866025,  0.500000,  0.00], tex_coords: [1.000000, 0.000000], normal: [ 0.866025,  0.500000, 0.0], image: 1 },
+    Vertex { position: [ 0.866025, -0.500000, -1.00], tex_coords: [0.000000, 1.000000], normal: [ 0.866025, -0.500000, 0.0], image: 1 },
+    Vertex { position: [ 0.000000, -1.000000, -1.00], tex_coords: [1.000000, 1.000000], normal: [ 0.000000, -1.000000, 0.0], image: 1 },
+    Vertex { position: [-0.866025, -0.500000, -1.00], tex_coords: [0.000000, 1.000000], normal: [-0.866025, -0.500000, 0.0], image: 1 },
+    Vertex { position: [-0.866025,  0.500000, -1.00], tex_coords: [1.000000, 1.000000], normal: [-0.866025,  0.500000, 0.0], image: 1 },
+    Vertex { position: [-0.000000,  1.000000, -1.00], tex_coords: [0.000000, 1.000000], normal: [ 0.000000,  1.000000, 0.0], image: 1 },
+    Vertex { position: [ 0.866025,  0.500000, -1.00], tex_coords: [1.000000, 1.000000], normal: [ 0.866025,  0.500000, 0.0], image: 1 },
 ];
 const INDICES: &[u16] = &[
     0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 4, 5, 0, 5, 6, 0, 6, 1, // bottom verts
@@ -93,35 +104,204 @@ impl Uniforms {
 struct InstanceRaw {
     model: nalgebra::Matrix4<f32>,
     texture_indexes: nalgebra::Vector4<u32>,
+    /// The alpha channel is how much of the tint to blend in.
+    tint: nalgebra::Vector4<f32>,
+    /// An alpha of zero means no outline.
+    outline: nalgebra::Vector4<f32>,
 }
 unsafe impl bytemuck::Pod for InstanceRaw {}
 unsafe impl bytemuck::Zeroable for InstanceRaw {}
+impl InstanceRaw {
+    /// A sphere around the hex prism, or None for a zeroed instance that isn't any tile at all.
+    fn bounds(&self) -> Option<(nalgebra::Point3<f32>, f32)> {
+        if self.model[(3, 3)] == 0.0 {
+            return None;
+        }
+
+        // the prism hangs down from the translation by however much it's scaled along z
+        let depth = self.model[(2, 2)];
+        let center = self
+            .model
+            .transform_point(&nalgebra::Point3::new(0.0, 0.0, -0.5));
+        Some((center, (1.0 + depth * depth / 4.0).sqrt()))
+    }
+
+    fn highlight(self, highlight: Option<Highlight>) -> Self {
+        let color = |c: Color| nalgebra::Vector4::new(c.r, c.g, c.b, c.a);
+
+        Self {
+            tint: highlight.map_or(nalgebra::Vector4::zeros(), |h| color(h.tint)),
+            outline: highlight
+                .and_then(|h| h.outline)
+                .map_or(nalgebra::Vector4::zeros(), color),
+            ..self
+        }
+    }
+}
+
+/// The instances for a column of tiles stacked on top of one another.
+fn column_instances(column: Vec<Tile>) -> Vec<InstanceRaw> {
+    let elevations = Tile::stack_elevations(&column).collect::<Vec<_>>();
+    column
+        .into_iter()
+        .zip(elevations)
+        .map(|(tile, elevation)| {
+            use nalgebra::Vector3 as Vec3;
+            let Tile {
+                position: p,
+                hat,
+                butt,
+                butt_size,
+                highlight,
+                ..
+            } = tile;
+            let world = p.to_world();
+            let position = Vec3::new(world.x, world.y, elevation);
+            let model = nalgebra::Matrix4::new_translation(&position)
+                * nalgebra::Matrix4::new_nonuniform_scaling(&Vec3::new(1.0, 1.0, butt_size));
+
+            InstanceRaw {
+                model,
+                texture_indexes: nalgebra::Vector4::new(hat.0, butt.0, 0, 0),
+                tint: nalgebra::Vector4::zeros(),
+                outline: nalgebra::Vector4::zeros(),
+            }
+            .highlight(highlight)
+        })
+        .collect()
+}
+
+const VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/hex/shader.vert.spv")),
+    "hex/shader.vert",
+);
+const FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/hex/shader.frag.spv")),
+    "hex/shader.frag",
+);
+const SHADOW_VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/hex_shadow/shader.vert.spv")),
+    "hex_shadow/shader.vert",
+);
+const SHADOW_FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/hex_shadow/shader.frag.spv")),
+    "hex_shadow/shader.frag",
+);
+
+fn diffuse_bind_group(
+    rs: &RenderingState,
+    layout: &wgpu::BindGroupLayout,
+    diffuse_texture: &texture::Texture,
+) -> wgpu::BindGroup {
+    rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &[
+            wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            },
+            wgpu::Binding {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+            },
+        ],
+        label: Some("diffuse_bind_group"),
+    })
+}
+
+fn render_pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): &(wgpu::ShaderModule, wgpu::ShaderModule),
+    config: &Config,
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: wgpu::CullMode::Back,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_read_mask: 0,
+                stencil_write_mask: 0,
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[Vertex::desc()],
+            },
+            sample_count: config.settings.msaa,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
 
 pub struct Hex {
     /// Stores vertex data
     vertex_buffer: wgpu::Buffer,
     /// Stores index data
     index_buffer: wgpu::Buffer,
+    shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    shadow_shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    shadow_pipeline_layout: wgpu::PipelineLayout,
+    shadow_pipeline: wgpu::RenderPipeline,
     uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
+    /// Like the uniform bind group, but with the instances the sun can see instead.
+    shadow_uniform_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     diffuse_texture: texture::Texture,
     diffuse_bind_group: wgpu::BindGroup,
-    instance_buffer: wgpu::Buffer,
+    instance_buffer: InstanceBuffer,
     instances_count: usize,
+    culling: Culling,
+    shadow_culling: Culling,
+    /// The instances of every tile, before any highlights from set_highlights are applied.
+    columns: Columns<InstanceRaw>,
+    highlights: HashMap<Offset, Highlight>,
 }
 
 impl Hex {
-    pub fn new(rs: &RenderingState, camera: &Camera, config: &Config) -> Self {
+    pub fn new(
+        rs: &RenderingState,
+        camera: &Camera,
+        config: &Config,
+        textures: &TextureSet,
+        shadow_map: &ShadowMap,
+    ) -> Result<Self, failure::Error> {
         // UNIFORMS
-        let instance_buffer_size =
-            (std::mem::size_of::<InstanceRaw>() * 250) as wgpu::BufferAddress;
-        let instance_buffer = rs.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("tile instance buffer"),
-            size: instance_buffer_size,
-            usage: wgpu::BufferUsage::STORAGE_READ | wgpu::BufferUsage::COPY_DST,
-        });
+        let instance_buffer = InstanceBuffer::new(
+            rs,
+            "tile instance buffer",
+            std::mem::size_of::<InstanceRaw>(),
+        );
 
         let mut uniforms = Uniforms::new();
         uniforms.update_view_proj(&camera);
@@ -148,55 +328,40 @@ impl Hex {
                                 readonly: true,
                             },
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStage::VERTEX,
+                            ty: wgpu::BindingType::StorageBuffer {
+                                dynamic: false,
+                                readonly: true,
+                            },
+                        },
                     ],
                     label: Some("uniform_bind_group_layout"),
                 });
-        let uniform_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            bindings: &[
-                wgpu::Binding {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer {
-                        buffer: &uniform_buffer,
-                        // FYI: you can share a single buffer between bindings.
-                        range: 0..std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
-                    },
-                },
-                wgpu::Binding {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer {
-                        buffer: &instance_buffer,
-                        range: 0..instance_buffer_size,
-                    },
-                },
-            ],
-            label: Some("uniform_bind_group"),
-        });
+        let culling = Culling::new(rs, "visible tile buffer");
+        let uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
+            &[&instance_buffer, &culling.visible],
+            "uniform_bind_group",
+        );
+        let mut shadow_culling = Culling::new(rs, "tiles visible to the sun");
+        shadow_culling.set_view_proj(shadow_map.light_view_proj());
+        let shadow_uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
+            &[&instance_buffer, &shadow_culling.visible],
+            "shadow_uniform_bind_group",
+        );
 
         // IMAGE
-        let (diffuse_texture, cmd_buffer) = texture::Texture::from_bytes(
-            &rs.device,
-            vec![
-                (
-                    include_bytes!("../../../img/hex/ice_hat.png"),
-                    "ice_hat.png",
-                ),
-                (
-                    include_bytes!("../../../img/hex/ice_butt.png"),
-                    "ice_butt.png",
-                ),
-                (
-                    include_bytes!("../../../img/hex/snow_hat.png"),
-                    "snow_hat.png",
-                ),
-                (
-                    include_bytes!("../../../img/hex/snow_butt.png"),
-                    "snow_butt.png",
-                ),
-            ],
-            "tile textures",
-        )
-        .unwrap();
+        let (diffuse_texture, cmd_buffer) =
+            texture::Texture::from_images(&rs.device, &textures.images, "tile textures")?;
         rs.queue.submit(&[cmd_buffer]);
 
         let texture_bind_group_layout =
@@ -221,20 +386,8 @@ impl Hex {
                     label: Some("texture_bind_group_layout"),
                 });
 
-        let diffuse_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            bindings: &[
-                wgpu::Binding {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::Binding {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
+        let diffuse_bind_group =
+            diffuse_bind_group(rs, &texture_bind_group_layout, &diffuse_texture);
 
         // VERTEXES (and by extension, indexes)
         let vertex_buffer = rs
@@ -245,79 +398,127 @@ impl Hex {
             .create_buffer_with_data(bytemuck::cast_slice(INDICES), wgpu::BufferUsage::INDEX);
 
         // SHADERS
-        let (vs_module, fs_module) = compile_shaders(
-            (
-                include_str!("../../../shader/hex/shader.vert"),
-                "hex/shader.vert",
-            ),
-            (
-                include_str!("../../../shader/hex/shader.frag"),
-                "hex/shader.frag",
-            ),
-            rs,
-        );
+        let shaders = load_shaders(VERTEX_SHADER, FRAGMENT_SHADER, rs)?;
 
         let render_pipeline_layout =
             rs.device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &uniform_bind_group_layout,
+                        &shadow_map.bind_group_layout,
+                    ],
                 });
-        let render_pipeline = rs
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &render_pipeline_layout,
-                vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vs_module,
-                    entry_point: "main",
-                },
-                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &fs_module,
-                    entry_point: "main",
-                }),
-                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: wgpu::CullMode::Back,
-                    depth_bias: 0,
-                    depth_bias_slope_scale: 0.0,
-                    depth_bias_clamp: 0.0,
-                }),
-                color_states: &[wgpu::ColorStateDescriptor {
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    color_blend: wgpu::BlendDescriptor::REPLACE,
-                    alpha_blend: wgpu::BlendDescriptor::REPLACE,
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-                depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
-                    format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_read_mask: 0,
-                    stencil_write_mask: 0,
-                }),
-                vertex_state: wgpu::VertexStateDescriptor {
-                    index_format: wgpu::IndexFormat::Uint16,
-                    vertex_buffers: &[Vertex::desc()],
-                },
-                sample_count: config.msaa,
-                sample_mask: !0,
-                alpha_to_coverage_enabled: false,
-            });
+        let render_pipeline = render_pipeline(rs, &render_pipeline_layout, &shaders, config);
 
-        Self {
+        // SHADOWS
+        let shadow_shaders = load_shaders(SHADOW_VERTEX_SHADER, SHADOW_FRAGMENT_SHADER, rs)?;
+        let shadow_pipeline_layout =
+            rs.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &uniform_bind_group_layout,
+                        &shadow_map.light_bind_group_layout,
+                    ],
+                });
+        let shadow_pipeline = shadow_map::pipeline(
+            rs,
+            &shadow_pipeline_layout,
+            &shadow_shaders,
+            &[Vertex::desc()],
+        );
+
+        Ok(Self {
             instances_count: 0,
+            culling,
+            shadow_culling,
+            columns: Columns::new(),
+            highlights: HashMap::new(),
+            shaders,
+            render_pipeline_layout,
             render_pipeline,
+            shadow_shaders,
+            shadow_pipeline_layout,
+            shadow_pipeline,
             vertex_buffer,
             index_buffer,
             uniforms,
             uniform_buffer,
+            uniform_bind_group_layout,
             uniform_bind_group,
+            shadow_uniform_bind_group,
+            texture_bind_group_layout,
             diffuse_texture,
             diffuse_bind_group,
             instance_buffer,
-        }
+        })
+    }
+
+    /// Rebuilds the render pipeline, for when the number of samples per pixel changes.
+    pub fn rebuild_pipeline(&mut self, rs: &RenderingState, config: &Config) {
+        self.render_pipeline =
+            render_pipeline(rs, &self.render_pipeline_layout, &self.shaders, config);
+    }
+
+    /// Recompiles the shaders from these sources and rebuilds the render pipeline with them,
+    /// keeping the old pipeline if they don't compile.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shaders(
+        &mut self,
+        rs: &RenderingState,
+        config: &Config,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, VERTEX_SHADER.1),
+            (fs_src, FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.render_pipeline = render_pipeline(rs, &self.render_pipeline_layout, &shaders, config);
+        self.shaders = shaders;
+        Ok(())
+    }
+
+    /// Like `reload_shaders`, but for the shaders tiles are drawn into the shadow map with.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shadow_shaders(
+        &mut self,
+        rs: &RenderingState,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, SHADOW_VERTEX_SHADER.1),
+            (fs_src, SHADOW_FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.shadow_pipeline = shadow_map::pipeline(
+            rs,
+            &self.shadow_pipeline_layout,
+            &shaders,
+            &[Vertex::desc()],
+        );
+        self.shadow_shaders = shaders;
+        Ok(())
+    }
+
+    /// Replaces the textures tiles are drawn with.
+    #[cfg(feature = "hot_reload")]
+    pub fn set_textures(
+        &mut self,
+        rs: &RenderingState,
+        textures: &TextureSet,
+    ) -> Result<(), failure::Error> {
+        let (diffuse_texture, cmd_buffer) =
+            texture::Texture::from_images(&rs.device, &textures.images, "tile textures")?;
+        rs.queue.submit(&[cmd_buffer]);
+
+        self.diffuse_bind_group =
+            diffuse_bind_group(rs, &self.texture_bind_group_layout, &diffuse_texture);
+        self.diffuse_texture = diffuse_texture;
+        Ok(())
     }
 
     pub fn set_camera(
@@ -327,6 +528,16 @@ impl Hex {
         camera: &Camera,
     ) {
         self.uniforms.update_view_proj(&camera);
+        self.culling.set_view_proj(self.uniforms.view_proj);
+        self.upload_uniforms(encoder, rs);
+    }
+
+    /// Which tiles need drawing into the shadow map depends on where the sun is looking.
+    pub fn set_light_view_proj(&mut self, light_view_proj: nalgebra::Matrix4<f32>) {
+        self.shadow_culling.set_view_proj(light_view_proj);
+    }
+
+    fn upload_uniforms(&self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
         let staging_buffer = rs.device.create_buffer_with_data(
             bytemuck::cast_slice(&[self.uniforms]),
             wgpu::BufferUsage::COPY_SRC,
@@ -347,47 +558,88 @@ impl Hex {
         rs: &RenderingState,
         tiles: Vec<Vec<Tile>>,
     ) {
-        let instance_data = tiles
-            .into_iter()
-            .flat_map(|column| {
-                column
-                    .into_iter()
-                    .enumerate()
-                    .scan(0.0_f32, |a, (i, mut tile)| {
-                        if i > 0 {
-                            *a += tile.butt_size;
-                            tile.elevation += *a;
-                        }
-                        Some(tile)
-                    })
-            })
-            .map(|t| {
-                use nalgebra::Vector3 as Vec3;
-                let Tile {
-                    elevation,
-                    position: p,
-                    hat,
-                    butt,
-                    butt_size,
-                } = t;
-                let w: f32 = 3.0_f32.sqrt();
-                let h: f32 = 2.0;
-
-                let position = Vec3::new(
-                    (p.x * 2 + (p.y & 1)) as f32 / 2.0 * w,
-                    ((3.0 / 4.0) * p.y as f32) * h,
-                    elevation,
-                );
-                let model = nalgebra::Matrix4::new_translation(&position)
-                    * nalgebra::Matrix4::new_nonuniform_scaling(&Vec3::new(1.0, 1.0, butt_size));
-
-                InstanceRaw {
-                    model,
-                    texture_indexes: nalgebra::Vector4::new(hat, butt, 0, 0),
-                }
+        self.columns.clear();
+        for column in tiles {
+            if let Some(position) = column.first().map(|t| t.position) {
+                self.columns.set(position, column_instances(column));
+            }
+        }
+
+        self.culling.instances_changed();
+        self.shadow_culling.instances_changed();
+        self.upload_instances(encoder, rs, 0..self.columns.len());
+    }
+
+    /// Replaces only the columns at the given positions, and only uploads the instances that changed.
+    pub fn update_tiles(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        columns: Vec<(Offset, Vec<Tile>)>,
+    ) {
+        let mut dirty = 0..0;
+        for (position, column) in columns {
+            let changed = self.columns.set(position, column_instances(column));
+            dirty = columns::union(dirty, changed);
+        }
+
+        self.culling.instances_changed();
+        self.shadow_culling.instances_changed();
+        if self.columns.needs_compacting() {
+            self.columns.compact();
+            dirty = 0..self.columns.len();
+        }
+
+        self.upload_instances(encoder, rs, dirty);
+    }
+
+    pub fn set_highlights(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        highlights: Vec<(Offset, Highlight)>,
+    ) {
+        let new = highlights.into_iter().collect::<HashMap<_, _>>();
+        let tiles = &self.columns;
+        let dirty = self
+            .highlights
+            .keys()
+            .chain(new.keys())
+            .filter_map(|p| tiles.range(p))
+            .fold(0..0, columns::union);
+
+        self.highlights = new;
+        self.upload_instances(encoder, rs, dirty);
+    }
+
+    /// Uploads the instances in the `dirty` range, or all of them if the buffer had to be resized.
+    fn upload_instances(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        mut dirty: std::ops::Range<usize>,
+    ) {
+        let count = self.columns.len();
+        if self.instance_buffer.reserve(rs, count) {
+            self.rebuild_uniform_bind_group(rs);
+            dirty = 0..count;
+        }
+        self.instances_count = count.min(self.instance_buffer.max_instances());
+
+        let dirty = dirty.start.min(self.instances_count)..dirty.end.min(self.instances_count);
+        if dirty.start == dirty.end {
+            return;
+        }
+
+        let highlights = &self.highlights;
+        let instance_data = self.columns.instances[dirty.clone()]
+            .iter()
+            .zip(&self.columns.positions[dirty.clone()])
+            .map(|(&instance, p)| match highlights.get(p) {
+                Some(&h) => instance.highlight(Some(h)),
+                None => instance,
             })
             .collect::<Vec<_>>();
-        self.instances_count = instance_data.len();
 
         let staging_buffer_size = instance_data.len() * std::mem::size_of::<InstanceRaw>();
         let staging_buffer = rs.device.create_buffer_with_data(
@@ -398,18 +650,84 @@ impl Hex {
         encoder.copy_buffer_to_buffer(
             &staging_buffer,
             0,
-            &self.instance_buffer,
-            0,
+            &self.instance_buffer.buffer,
+            (dirty.start * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
             staging_buffer_size as u64,
         );
     }
 
-    pub fn render<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+    fn rebuild_uniform_bind_group(&mut self, rs: &RenderingState) {
+        self.uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            &[&self.instance_buffer, &self.culling.visible],
+            "uniform_bind_group",
+        );
+        self.shadow_uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            &[&self.instance_buffer, &self.shadow_culling.visible],
+            "shadow_uniform_bind_group",
+        );
+    }
+
+    /// Figures out which instances are on screen and which the sun can see,
+    /// if the camera, the sun or the instances have changed.
+    pub fn cull(&mut self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
+        let instances = &self.columns.instances[..self.instances_count];
+        let bounds = || instances.iter().map(InstanceRaw::bounds);
+
+        let mut reallocated = false;
+        if self.culling.is_dirty() {
+            reallocated |= self.culling.cull(encoder, rs, bounds());
+        }
+        if self.shadow_culling.is_dirty() {
+            reallocated |= self.shadow_culling.cull(encoder, rs, bounds());
+        }
+        if reallocated {
+            self.rebuild_uniform_bind_group(rs);
+        }
+    }
+
+    /// How many instances were drawn, and how many were culled.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.culling.drawn, self.culling.culled)
+    }
+
+    pub fn render<'a>(
+        &'a mut self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_map: &'a ShadowMap,
+    ) {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); // NEW!
         render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &shadow_map.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.culling.drawn as u32);
+    }
+
+    /// Draws the tiles the sun can see into the shadow map.
+    pub fn render_shadow<'a>(
+        &'a mut self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_map: &'a ShadowMap,
+    ) {
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.shadow_uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &shadow_map.light_bind_group, &[]);
         render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
         render_pass.set_index_buffer(&self.index_buffer, 0, 0);
-        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.instances_count as u32);
+        render_pass.draw_indexed(
+            0..INDICES.len() as u32,
+            0,
+            0..self.shadow_culling.drawn as u32,
+        );
     }
 }
@@ -0,0 +1,113 @@
+use crate::RenderingState;
+use hexa::iced_wgpu::wgpu;
+
+/// How many instances there's room for before anything's been uploaded.
+const INITIAL_CAPACITY: usize = 256;
+/// Vulkan only guarantees storage buffer bindings up to this many bytes.
+pub const MAX_SIZE: wgpu::BufferAddress = 1 << 27;
+
+/// A storage buffer of per-instance data that grows when more instances are uploaded than it can
+/// hold, and shrinks when far fewer are. Whenever it's reallocated, any bind groups referencing it
+/// must be rebuilt, so `reserve` says when that happens.
+pub struct InstanceBuffer {
+    pub buffer: wgpu::Buffer,
+    label: &'static str,
+    /// The size of a single instance, in bytes.
+    stride: usize,
+    /// How many instances fit in the buffer.
+    capacity: usize,
+}
+impl InstanceBuffer {
+    pub fn new(rs: &RenderingState, label: &'static str, stride: usize) -> Self {
+        Self {
+            buffer: Self::allocate(rs, label, stride, INITIAL_CAPACITY),
+            label,
+            stride,
+            capacity: INITIAL_CAPACITY,
+        }
+    }
+
+    fn allocate(rs: &RenderingState, label: &str, stride: usize, capacity: usize) -> wgpu::Buffer {
+        rs.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (stride * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::STORAGE_READ | wgpu::BufferUsage::COPY_DST,
+        })
+    }
+
+    /// The most instances a buffer is ever allowed to hold.
+    pub fn max_instances(&self) -> usize {
+        MAX_SIZE as usize / self.stride
+    }
+
+    pub fn size(&self) -> wgpu::BufferAddress {
+        (self.stride * self.capacity) as wgpu::BufferAddress
+    }
+
+    /// Makes sure there's room for `count` instances, reallocating the buffer if there isn't or if
+    /// it's more than four times larger than it needs to be. The old contents are not preserved.
+    /// Returns whether or not the buffer was reallocated.
+    ///
+    /// Anything beyond `max_instances` can't be stored, so callers must clamp what they upload.
+    pub fn reserve(&mut self, rs: &RenderingState, count: usize) -> bool {
+        let max = self.max_instances();
+        if count > max {
+            log::error!(
+                "{} can't hold {} instances, only the first {} will be drawn",
+                self.label,
+                count,
+                max
+            );
+        }
+        let count = count.min(max);
+
+        let too_small = count > self.capacity;
+        let too_big = self.capacity > INITIAL_CAPACITY && count < self.capacity / 4;
+        if !too_small && !too_big {
+            return false;
+        }
+
+        let capacity = count.next_power_of_two().max(INITIAL_CAPACITY).min(max);
+        log::debug!(
+            "resizing {} from {} to {} instances",
+            self.label,
+            self.capacity,
+            capacity
+        );
+        self.buffer = Self::allocate(rs, self.label, self.stride, capacity);
+        self.capacity = capacity;
+        true
+    }
+}
+
+/// A bind group with a uniform buffer at binding 0, followed by each of the `buffers` in order.
+pub fn bind_group(
+    rs: &RenderingState,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    uniforms_size: wgpu::BufferAddress,
+    buffers: &[&InstanceBuffer],
+    label: &str,
+) -> wgpu::BindGroup {
+    let bindings = std::iter::once(wgpu::Binding {
+        binding: 0,
+        resource: wgpu::BindingResource::Buffer {
+            buffer: uniform_buffer,
+            range: 0..uniforms_size,
+        },
+    })
+    .chain(buffers.iter().enumerate().map(|(i, b)| wgpu::Binding {
+        binding: i as u32 + 1,
+        resource: wgpu::BindingResource::Buffer {
+            buffer: &b.buffer,
+            range: 0..b.size(),
+        },
+    }))
+    .collect::<Vec<_>>();
+
+    rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &bindings,
+        label: Some(label),
+    })
+}
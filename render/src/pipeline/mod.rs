@@ -1,7 +1,17 @@
+mod columns;
+mod culling;
 pub mod fullscreen_triangle;
 pub mod hex;
+mod instance_buffer;
 pub mod quad;
+pub mod sky;
+pub mod water;
 
+use columns::Columns;
+use culling::Culling;
 pub use fullscreen_triangle::FullscreenTriangle as FullscreenTrianglePipeline;
 pub use hex::Hex as HexPipeline;
+use instance_buffer::InstanceBuffer;
 pub use quad::Quad as QuadPipeline;
+pub use sky::Sky as SkyPipeline;
+pub use water::Water as WaterPipeline;
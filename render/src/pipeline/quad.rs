@@ -1,5 +1,7 @@
-use crate::Config;
-use crate::{compile_shaders, texture, RenderingState, Sprite};
+use super::{instance_buffer, Culling, InstanceBuffer};
+use crate::shader::load_shaders;
+use crate::{assets::TextureSet, shadow_map, Config, ShadowMap};
+use crate::{texture, RenderingState, Sprite};
 use hexa::{iced_wgpu::wgpu, Camera};
 
 #[repr(C)]
@@ -72,32 +74,152 @@ struct InstanceRaw {
 }
 unsafe impl bytemuck::Pod for InstanceRaw {}
 unsafe impl bytemuck::Zeroable for InstanceRaw {}
+impl InstanceRaw {
+    /// A sphere around the quad, however it ends up facing the camera.
+    fn bounds(&self) -> Option<(nalgebra::Point3<f32>, f32)> {
+        let radius = self.scale.x.max(self.scale.y) * std::f32::consts::FRAC_1_SQRT_2;
+        Some((self.position.into(), radius))
+    }
+}
+
+const VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/quad/shader.vert.spv")),
+    "quad/shader.vert",
+);
+const FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/quad/shader.frag.spv")),
+    "quad/shader.frag",
+);
+const SHADOW_VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/quad_shadow/shader.vert.spv")),
+    "quad_shadow/shader.vert",
+);
+const SHADOW_FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/quad_shadow/shader.frag.spv")),
+    "quad_shadow/shader.frag",
+);
+
+fn diffuse_bind_group(
+    rs: &RenderingState,
+    layout: &wgpu::BindGroupLayout,
+    diffuse_texture: &texture::Texture,
+) -> wgpu::BindGroup {
+    rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        bindings: &[
+            wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
+            },
+            wgpu::Binding {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
+            },
+        ],
+        label: Some("quad_diffuse_bind_group"),
+    })
+}
+
+fn render_pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): &(wgpu::ShaderModule, wgpu::ShaderModule),
+    config: &Config,
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: wgpu::CullMode::Back,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                color_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Max,
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_read_mask: 0,
+                stencil_write_mask: 0,
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[Vertex::desc()],
+            },
+            sample_count: config.settings.msaa,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
 
 pub struct Quad {
     /// Stores vertex data
     vertex_buffer: wgpu::Buffer,
     /// Stores index data
     index_buffer: wgpu::Buffer,
+    shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    render_pipeline_layout: wgpu::PipelineLayout,
     render_pipeline: wgpu::RenderPipeline,
+    shadow_shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    shadow_pipeline_layout: wgpu::PipelineLayout,
+    shadow_pipeline: wgpu::RenderPipeline,
     uniforms: Uniforms,
     uniform_buffer: wgpu::Buffer,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
     uniform_bind_group: wgpu::BindGroup,
+    /// Like the uniform bind group, but with the instances the sun can see instead.
+    shadow_uniform_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
     diffuse_texture: texture::Texture,
     diffuse_bind_group: wgpu::BindGroup,
-    instance_buffer: wgpu::Buffer,
+    instance_buffer: InstanceBuffer,
     instances_count: usize,
+    /// What was last uploaded via set_sprites, kept around for culling.
+    instances: Vec<InstanceRaw>,
+    culling: Culling,
+    shadow_culling: Culling,
 }
 
 impl Quad {
-    pub fn new(rs: &RenderingState, camera: &Camera, config: &Config) -> Self {
+    pub fn new(
+        rs: &RenderingState,
+        camera: &Camera,
+        config: &Config,
+        textures: &TextureSet,
+        shadow_map: &ShadowMap,
+    ) -> Result<Self, failure::Error> {
         // UNIFORMS
-        let instance_buffer_size =
-            (std::mem::size_of::<InstanceRaw>() * 250) as wgpu::BufferAddress;
-        let instance_buffer = rs.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("quad instance buffer"),
-            size: instance_buffer_size,
-            usage: wgpu::BufferUsage::STORAGE_READ | wgpu::BufferUsage::COPY_DST,
-        });
+        let instance_buffer = InstanceBuffer::new(
+            rs,
+            "quad instance buffer",
+            std::mem::size_of::<InstanceRaw>(),
+        );
 
         let mut uniforms = Uniforms::new();
         uniforms.update_view_proj(&camera);
@@ -124,38 +246,40 @@ impl Quad {
                                 readonly: true,
                             },
                         },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStage::VERTEX,
+                            ty: wgpu::BindingType::StorageBuffer {
+                                dynamic: false,
+                                readonly: true,
+                            },
+                        },
                     ],
                     label: Some("quad_uniform_bind_group_layout"),
                 });
-        let uniform_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniform_bind_group_layout,
-            bindings: &[
-                wgpu::Binding {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Buffer {
-                        buffer: &uniform_buffer,
-                        // FYI: you can share a single buffer between bindings.
-                        range: 0..std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
-                    },
-                },
-                wgpu::Binding {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Buffer {
-                        buffer: &instance_buffer,
-                        range: 0..instance_buffer_size,
-                    },
-                },
-            ],
-            label: Some("quad_uniform_bind_group"),
-        });
+        let culling = Culling::new(rs, "visible quad buffer");
+        let uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
+            &[&instance_buffer, &culling.visible],
+            "quad_uniform_bind_group",
+        );
+        let mut shadow_culling = Culling::new(rs, "quads visible to the sun");
+        shadow_culling.set_view_proj(shadow_map.light_view_proj());
+        let shadow_uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &uniform_bind_group_layout,
+            &uniform_buffer,
+            std::mem::size_of_val(&uniforms) as wgpu::BufferAddress,
+            &[&instance_buffer, &shadow_culling.visible],
+            "quad_shadow_uniform_bind_group",
+        );
 
         // IMAGE
-        let (diffuse_texture, cmd_buffer) = texture::Texture::from_bytes(
-            &rs.device,
-            vec![(include_bytes!("../../../img/sprite/stump.png"), "stump.png")],
-            "quad textures",
-        )
-        .unwrap();
+        let (diffuse_texture, cmd_buffer) =
+            texture::Texture::from_images(&rs.device, &textures.images, "quad textures")?;
         rs.queue.submit(&[cmd_buffer]);
 
         let texture_bind_group_layout =
@@ -180,20 +304,8 @@ impl Quad {
                     label: Some("quad_texture_bind_group_layout"),
                 });
 
-        let diffuse_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            bindings: &[
-                wgpu::Binding {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::Binding {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-            ],
-            label: Some("quad_diffuse_bind_group"),
-        });
+        let diffuse_bind_group =
+            diffuse_bind_group(rs, &texture_bind_group_layout, &diffuse_texture);
 
         // VERTEXES (and by extension, indexes)
         let vertex_buffer = rs
@@ -204,87 +316,126 @@ impl Quad {
             .create_buffer_with_data(bytemuck::cast_slice(INDICES), wgpu::BufferUsage::INDEX);
 
         // SHADERS
-        let (vs_module, fs_module) = compile_shaders(
-            (
-                include_str!("../../../shader/quad/shader.vert"),
-                "quad/shader.vert",
-            ),
-            (
-                include_str!("../../../shader/quad/shader.frag"),
-                "quad/shader.frag",
-            ),
-            rs,
-        );
+        let shaders = load_shaders(VERTEX_SHADER, FRAGMENT_SHADER, rs)?;
 
         let render_pipeline_layout =
             rs.device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&texture_bind_group_layout, &uniform_bind_group_layout],
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &uniform_bind_group_layout,
+                        &shadow_map.bind_group_layout,
+                    ],
                 });
-        let render_pipeline = rs
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                layout: &render_pipeline_layout,
-                vertex_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &vs_module,
-                    entry_point: "main",
-                },
-                fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
-                    module: &fs_module,
-                    entry_point: "main",
-                }),
-                rasterization_state: Some(wgpu::RasterizationStateDescriptor {
-                    front_face: wgpu::FrontFace::Cw,
-                    cull_mode: wgpu::CullMode::Back,
-                    depth_bias: 0,
-                    depth_bias_slope_scale: 0.0,
-                    depth_bias_clamp: 0.0,
-                }),
-                color_states: &[wgpu::ColorStateDescriptor {
-                    format: wgpu::TextureFormat::Rgba8Unorm,
-                    color_blend: wgpu::BlendDescriptor {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha_blend: wgpu::BlendDescriptor {
-                        src_factor: wgpu::BlendFactor::One,
-                        dst_factor: wgpu::BlendFactor::One,
-                        operation: wgpu::BlendOperation::Max,
-                    },
-                    write_mask: wgpu::ColorWrite::ALL,
-                }],
-                primitive_topology: wgpu::PrimitiveTopology::TriangleList,
-                depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
-                    format: texture::Texture::DEPTH_FORMAT,
-                    depth_write_enabled: true,
-                    depth_compare: wgpu::CompareFunction::Less,
-                    stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
-                    stencil_read_mask: 0,
-                    stencil_write_mask: 0,
-                }),
-                vertex_state: wgpu::VertexStateDescriptor {
-                    index_format: wgpu::IndexFormat::Uint16,
-                    vertex_buffers: &[Vertex::desc()],
-                },
-                sample_count: config.msaa,
-                sample_mask: !0,
-                alpha_to_coverage_enabled: false,
-            });
+        let render_pipeline = render_pipeline(rs, &render_pipeline_layout, &shaders, config);
 
-        Self {
+        // SHADOWS
+        let shadow_shaders = load_shaders(SHADOW_VERTEX_SHADER, SHADOW_FRAGMENT_SHADER, rs)?;
+        let shadow_pipeline_layout =
+            rs.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &uniform_bind_group_layout,
+                        &shadow_map.light_bind_group_layout,
+                    ],
+                });
+        let shadow_pipeline = shadow_map::pipeline(
+            rs,
+            &shadow_pipeline_layout,
+            &shadow_shaders,
+            &[Vertex::desc()],
+        );
+
+        Ok(Self {
             instances_count: 0,
+            instances: Vec::new(),
+            culling,
+            shadow_culling,
+            shaders,
+            render_pipeline_layout,
             render_pipeline,
+            shadow_shaders,
+            shadow_pipeline_layout,
+            shadow_pipeline,
             vertex_buffer,
             index_buffer,
             uniforms,
             uniform_buffer,
+            uniform_bind_group_layout,
             uniform_bind_group,
+            shadow_uniform_bind_group,
+            texture_bind_group_layout,
             diffuse_texture,
             diffuse_bind_group,
             instance_buffer,
-        }
+        })
+    }
+
+    /// Rebuilds the render pipeline, for when the number of samples per pixel changes.
+    pub fn rebuild_pipeline(&mut self, rs: &RenderingState, config: &Config) {
+        self.render_pipeline =
+            render_pipeline(rs, &self.render_pipeline_layout, &self.shaders, config);
+    }
+
+    /// Recompiles the shaders from these sources and rebuilds the render pipeline with them,
+    /// keeping the old pipeline if they don't compile.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shaders(
+        &mut self,
+        rs: &RenderingState,
+        config: &Config,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, VERTEX_SHADER.1),
+            (fs_src, FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.render_pipeline = render_pipeline(rs, &self.render_pipeline_layout, &shaders, config);
+        self.shaders = shaders;
+        Ok(())
+    }
+
+    /// Like `reload_shaders`, but for the shaders sprites are drawn into the shadow map with.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shadow_shaders(
+        &mut self,
+        rs: &RenderingState,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, SHADOW_VERTEX_SHADER.1),
+            (fs_src, SHADOW_FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.shadow_pipeline = shadow_map::pipeline(
+            rs,
+            &self.shadow_pipeline_layout,
+            &shaders,
+            &[Vertex::desc()],
+        );
+        self.shadow_shaders = shaders;
+        Ok(())
+    }
+
+    /// Replaces the textures sprites are drawn with.
+    #[cfg(feature = "hot_reload")]
+    pub fn set_textures(
+        &mut self,
+        rs: &RenderingState,
+        textures: &TextureSet,
+    ) -> Result<(), failure::Error> {
+        let (diffuse_texture, cmd_buffer) =
+            texture::Texture::from_images(&rs.device, &textures.images, "quad textures")?;
+        rs.queue.submit(&[cmd_buffer]);
+
+        self.diffuse_bind_group =
+            diffuse_bind_group(rs, &self.texture_bind_group_layout, &diffuse_texture);
+        self.diffuse_texture = diffuse_texture;
+        Ok(())
     }
 
     pub fn set_camera(
@@ -294,6 +445,7 @@ impl Quad {
         camera: &Camera,
     ) {
         self.uniforms.update_view_proj(&camera);
+        self.culling.set_view_proj(self.uniforms.view_proj);
         let staging_buffer = rs.device.create_buffer_with_data(
             bytemuck::cast_slice(&[self.uniforms]),
             wgpu::BufferUsage::COPY_SRC,
@@ -308,12 +460,21 @@ impl Quad {
         );
     }
 
+    /// Which sprites need drawing into the shadow map depends on where the sun is looking.
+    pub fn set_light_view_proj(&mut self, light_view_proj: nalgebra::Matrix4<f32>) {
+        self.shadow_culling.set_view_proj(light_view_proj);
+    }
+
     pub fn set_sprites(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
         rs: &RenderingState,
         sprites: Vec<Sprite>,
     ) {
+        if self.instance_buffer.reserve(rs, sprites.len()) {
+            self.rebuild_uniform_bind_group(rs);
+        }
+
         let instance_data = sprites
             .into_iter()
             .map(|t| {
@@ -327,9 +488,10 @@ impl Quad {
                 InstanceRaw {
                     position: Vec3::new(pos.x, pos.y, 0.275),
                     scale: Vec3::new(scale.x, scale.y, 1.0),
-                    texture_indexes: nalgebra::Vector2::x() * image,
+                    texture_indexes: nalgebra::Vector2::x() * image.0,
                 }
             })
+            .take(self.instance_buffer.max_instances())
             .collect::<Vec<_>>();
         self.instances_count = instance_data.len();
 
@@ -342,18 +504,88 @@ impl Quad {
         encoder.copy_buffer_to_buffer(
             &staging_buffer,
             0,
-            &self.instance_buffer,
+            &self.instance_buffer.buffer,
             0,
             staging_buffer_size as u64,
         );
+
+        self.instances = instance_data;
+        self.culling.instances_changed();
+        self.shadow_culling.instances_changed();
     }
 
-    pub fn render<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+    fn rebuild_uniform_bind_group(&mut self, rs: &RenderingState) {
+        self.uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            &[&self.instance_buffer, &self.culling.visible],
+            "quad_uniform_bind_group",
+        );
+        self.shadow_uniform_bind_group = instance_buffer::bind_group(
+            rs,
+            &self.uniform_bind_group_layout,
+            &self.uniform_buffer,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            &[&self.instance_buffer, &self.shadow_culling.visible],
+            "quad_shadow_uniform_bind_group",
+        );
+    }
+
+    /// Figures out which instances are on screen and which the sun can see,
+    /// if the camera, the sun or the instances have changed.
+    pub fn cull(&mut self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
+        let instances = &self.instances[..self.instances_count];
+        let bounds = || instances.iter().map(InstanceRaw::bounds);
+
+        let mut reallocated = false;
+        if self.culling.is_dirty() {
+            reallocated |= self.culling.cull(encoder, rs, bounds());
+        }
+        if self.shadow_culling.is_dirty() {
+            reallocated |= self.shadow_culling.cull(encoder, rs, bounds());
+        }
+        if reallocated {
+            self.rebuild_uniform_bind_group(rs);
+        }
+    }
+
+    /// How many instances were drawn, and how many were culled.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.culling.drawn, self.culling.culled)
+    }
+
+    pub fn render<'a>(
+        &'a mut self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_map: &'a ShadowMap,
+    ) {
         render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]); // NEW!
         render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &shadow_map.bind_group, &[]);
         render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
         render_pass.set_index_buffer(&self.index_buffer, 0, 0);
-        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.instances_count as u32);
+        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..self.culling.drawn as u32);
+    }
+
+    /// Draws the sprites the sun can see into the shadow map.
+    pub fn render_shadow<'a>(
+        &'a mut self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_map: &'a ShadowMap,
+    ) {
+        render_pass.set_pipeline(&self.shadow_pipeline);
+        render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.shadow_uniform_bind_group, &[]);
+        render_pass.set_bind_group(2, &shadow_map.light_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, &self.vertex_buffer, 0, 0);
+        render_pass.set_index_buffer(&self.index_buffer, 0, 0);
+        render_pass.draw_indexed(
+            0..INDICES.len() as u32,
+            0,
+            0..self.shadow_culling.drawn as u32,
+        );
     }
 }
@@ -0,0 +1,236 @@
+use crate::shader::load_shaders;
+use crate::{texture, Config, RenderingState};
+use hexa::{iced_wgpu::wgpu, iced_winit::Color, Camera};
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Uniforms {
+    /// For working out which way the camera is looking through each pixel.
+    inverse_view_proj: nalgebra::Matrix4<f32>,
+    zenith: nalgebra::Vector4<f32>,
+    horizon: nalgebra::Vector4<f32>,
+}
+unsafe impl bytemuck::Pod for Uniforms {}
+unsafe impl bytemuck::Zeroable for Uniforms {}
+impl Uniforms {
+    fn new() -> Self {
+        Self {
+            inverse_view_proj: nalgebra::Matrix4::identity(),
+            zenith: nalgebra::Vector4::zeros(),
+            horizon: nalgebra::Vector4::zeros(),
+        }
+    }
+
+    fn update_view_proj(&mut self, camera: &Camera) {
+        self.inverse_view_proj = camera
+            .build_view_projection_matrix()
+            .try_inverse()
+            .unwrap_or_else(nalgebra::Matrix4::identity);
+    }
+
+    fn update_sky(&mut self, sky: &hexa::Sky) {
+        let color = |c: Color| nalgebra::Vector4::new(c.r, c.g, c.b, c.a);
+        self.zenith = color(sky.zenith);
+        self.horizon = color(sky.horizon);
+    }
+}
+
+const VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/sky/shader.vert.spv")),
+    "sky/shader.vert",
+);
+const FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/sky/shader.frag.spv")),
+    "sky/shader.frag",
+);
+
+fn render_pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): &(wgpu::ShaderModule, wgpu::ShaderModule),
+    config: &Config,
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::Back,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            // drawn before everything else, and never in front of any of it
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_read_mask: 0,
+                stencil_write_mask: 0,
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[],
+            },
+            sample_count: config.settings.msaa,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
+
+/// A gradient behind everything else, drawn with one triangle that covers the whole screen.
+pub struct Sky {
+    shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    render_pipeline_layout: wgpu::PipelineLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    uniforms: Uniforms,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    /// Nothing is drawn until there's a sky to draw, so the background color shows instead.
+    visible: bool,
+}
+impl Sky {
+    pub fn new(
+        rs: &RenderingState,
+        camera: &Camera,
+        config: &Config,
+    ) -> Result<Self, failure::Error> {
+        let mut uniforms = Uniforms::new();
+        uniforms.update_view_proj(camera);
+
+        let uniform_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let uniform_bind_group_layout =
+            rs.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    bindings: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    }],
+                    label: Some("sky_uniform_bind_group_layout"),
+                });
+        let uniform_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            bindings: &[wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: &uniform_buffer,
+                    range: 0..std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+                },
+            }],
+            label: Some("sky_uniform_bind_group"),
+        });
+
+        let shaders = load_shaders(VERTEX_SHADER, FRAGMENT_SHADER, rs)?;
+
+        let render_pipeline_layout =
+            rs.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    bind_group_layouts: &[&uniform_bind_group_layout],
+                });
+        let render_pipeline = render_pipeline(rs, &render_pipeline_layout, &shaders, config);
+
+        Ok(Self {
+            shaders,
+            render_pipeline_layout,
+            render_pipeline,
+            uniforms,
+            uniform_buffer,
+            uniform_bind_group,
+            visible: false,
+        })
+    }
+
+    /// Rebuilds the render pipeline, for when the number of samples per pixel changes.
+    pub fn rebuild_pipeline(&mut self, rs: &RenderingState, config: &Config) {
+        self.render_pipeline =
+            render_pipeline(rs, &self.render_pipeline_layout, &self.shaders, config);
+    }
+
+    /// Recompiles the shaders from these sources and rebuilds the render pipeline with them,
+    /// keeping the old pipeline if they don't compile.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shaders(
+        &mut self,
+        rs: &RenderingState,
+        config: &Config,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, VERTEX_SHADER.1),
+            (fs_src, FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.render_pipeline = render_pipeline(rs, &self.render_pipeline_layout, &shaders, config);
+        self.shaders = shaders;
+        Ok(())
+    }
+
+    pub fn set_camera(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        camera: &Camera,
+    ) {
+        self.uniforms.update_view_proj(camera);
+        self.upload_uniforms(encoder, rs);
+    }
+
+    pub fn set_sky(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        sky: &hexa::Sky,
+    ) {
+        self.uniforms.update_sky(sky);
+        self.visible = true;
+        self.upload_uniforms(encoder, rs);
+    }
+
+    fn upload_uniforms(&self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
+        let staging_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[self.uniforms]),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.uniform_buffer,
+            0,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+        );
+    }
+
+    pub fn render<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.visible {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,273 @@
+use crate::shader::load_shaders;
+use crate::{texture, Config, RenderingState, ShadowMap};
+use hexa::{iced_wgpu::wgpu, Camera};
+
+/// How far the water reaches from what the camera is looking at, in world units.
+/// Far enough that its edges are never on screen before the camera's far plane cuts it off.
+const REACH: f32 = 200.0;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Uniforms {
+    view_proj: nalgebra::Matrix4<f32>,
+    /// xy is the middle of the water, z is sea level, w is how far it reaches from the middle.
+    plane: nalgebra::Vector4<f32>,
+    eye: nalgebra::Vector4<f32>,
+    /// x is how many seconds the waves have been moving for.
+    time: nalgebra::Vector4<f32>,
+}
+unsafe impl bytemuck::Pod for Uniforms {}
+unsafe impl bytemuck::Zeroable for Uniforms {}
+impl Uniforms {
+    fn new() -> Self {
+        Self {
+            view_proj: nalgebra::Matrix4::identity(),
+            plane: nalgebra::Vector4::new(0.0, 0.0, 0.0, REACH),
+            eye: nalgebra::Vector4::zeros(),
+            time: nalgebra::Vector4::zeros(),
+        }
+    }
+
+    fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_proj = camera.build_view_projection_matrix();
+        self.plane.x = camera.target.x;
+        self.plane.y = camera.target.y;
+        self.eye = (camera.eye.coords + camera.target.coords).push(1.0);
+    }
+}
+
+const VERTEX_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/water/shader.vert.spv")),
+    "water/shader.vert",
+);
+const FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/water/shader.frag.spv")),
+    "water/shader.frag",
+);
+
+fn render_pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): &(wgpu::ShaderModule, wgpu::ShaderModule),
+    config: &Config,
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            // the water can be seen from beneath, when the camera is lower than sea level
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: wgpu::CullMode::None,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &[wgpu::ColorStateDescriptor {
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                color_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha_blend: wgpu::BlendDescriptor {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Max,
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            // hidden by whatever's in front of it, but never hiding whatever's behind it
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_read_mask: 0,
+                stencil_write_mask: 0,
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[],
+            },
+            sample_count: config.settings.msaa,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
+
+/// A sheet of see-through water at sea level, following the camera around.
+/// It's drawn after everything else, since anything beneath it has to be drawn already
+/// for it to show through.
+pub struct Water {
+    shaders: (wgpu::ShaderModule, wgpu::ShaderModule),
+    render_pipeline_layout: wgpu::PipelineLayout,
+    render_pipeline: wgpu::RenderPipeline,
+    uniforms: Uniforms,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    /// Nothing is drawn until there's a sea level to draw the water at.
+    visible: bool,
+}
+impl Water {
+    pub fn new(
+        rs: &RenderingState,
+        camera: &Camera,
+        config: &Config,
+        shadow_map: &ShadowMap,
+    ) -> Result<Self, failure::Error> {
+        let mut uniforms = Uniforms::new();
+        uniforms.update_view_proj(camera);
+
+        let uniform_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+
+        let uniform_bind_group_layout =
+            rs.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    bindings: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    }],
+                    label: Some("water_uniform_bind_group_layout"),
+                });
+        let uniform_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_bind_group_layout,
+            bindings: &[wgpu::Binding {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer {
+                    buffer: &uniform_buffer,
+                    range: 0..std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+                },
+            }],
+            label: Some("water_uniform_bind_group"),
+        });
+
+        let shaders = load_shaders(VERTEX_SHADER, FRAGMENT_SHADER, rs)?;
+
+        let render_pipeline_layout =
+            rs.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    bind_group_layouts: &[
+                        &uniform_bind_group_layout,
+                        &shadow_map.bind_group_layout,
+                    ],
+                });
+        let render_pipeline = render_pipeline(rs, &render_pipeline_layout, &shaders, config);
+
+        Ok(Self {
+            shaders,
+            render_pipeline_layout,
+            render_pipeline,
+            uniforms,
+            uniform_buffer,
+            uniform_bind_group,
+            visible: false,
+        })
+    }
+
+    /// Rebuilds the render pipeline, for when the number of samples per pixel changes.
+    pub fn rebuild_pipeline(&mut self, rs: &RenderingState, config: &Config) {
+        self.render_pipeline =
+            render_pipeline(rs, &self.render_pipeline_layout, &self.shaders, config);
+    }
+
+    /// Recompiles the shaders from these sources and rebuilds the render pipeline with them,
+    /// keeping the old pipeline if they don't compile.
+    #[cfg(feature = "hot_reload")]
+    pub fn reload_shaders(
+        &mut self,
+        rs: &RenderingState,
+        config: &Config,
+        vs_src: &str,
+        fs_src: &str,
+    ) -> Result<(), crate::shader::ShaderError> {
+        let shaders = crate::shader::compile_shaders(
+            (vs_src, VERTEX_SHADER.1),
+            (fs_src, FRAGMENT_SHADER.1),
+            rs,
+        )?;
+        self.render_pipeline = render_pipeline(rs, &self.render_pipeline_layout, &shaders, config);
+        self.shaders = shaders;
+        Ok(())
+    }
+
+    pub fn set_camera(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        camera: &Camera,
+    ) {
+        self.uniforms.update_view_proj(camera);
+        self.upload_uniforms(encoder, rs);
+    }
+
+    /// Moves the water to this height, or drains it for None.
+    pub fn set_sea_level(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        sea_level: Option<f32>,
+    ) {
+        self.visible = sea_level.is_some();
+        if let Some(sea_level) = sea_level {
+            self.uniforms.plane.z = sea_level;
+            self.upload_uniforms(encoder, rs);
+        }
+    }
+
+    /// Moves the waves along to where they should be this many seconds in.
+    pub fn set_time(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        seconds: f32,
+    ) {
+        if self.visible && self.uniforms.time.x != seconds {
+            self.uniforms.time.x = seconds;
+            self.upload_uniforms(encoder, rs);
+        }
+    }
+
+    fn upload_uniforms(&self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
+        let staging_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[self.uniforms]),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.uniform_buffer,
+            0,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+        );
+    }
+
+    pub fn render<'a>(
+        &'a mut self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        shadow_map: &'a ShadowMap,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &shadow_map.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
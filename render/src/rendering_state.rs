@@ -1,3 +1,4 @@
+use crate::RenderSettings;
 use hexa::{iced_wgpu, iced_winit};
 use iced_wgpu::{wgpu, Viewport};
 use iced_winit::winit;
@@ -5,22 +6,40 @@ use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
 pub struct RenderingState {
-    pub surface: wgpu::Surface,
+    /// Which adapter was picked, and what kind it is.
+    pub adapter_info: wgpu::AdapterInfo,
+    /// `None` when rendering headlessly into an offscreen texture.
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub swap_chain: wgpu::SwapChain,
+    /// `None` when rendering headlessly into an offscreen texture.
+    pub swap_chain: Option<wgpu::SwapChain>,
+    /// Describes whatever we're drawing into; for headless rendering, this is
+    /// the size and format of the offscreen texture, and no swap chain is built from it.
     pub swap_chain_descriptor: wgpu::SwapChainDescriptor,
     pub viewport: Viewport,
 }
 
+async fn request_device(adapter: wgpu::Adapter) -> (wgpu::AdapterInfo, wgpu::Device, wgpu::Queue) {
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            extensions: wgpu::Extensions {
+                anisotropic_filtering: false,
+            },
+            limits: wgpu::Limits::default(),
+        })
+        .await;
+    (adapter.get_info(), device, queue)
+}
+
 impl RenderingState {
-    pub fn new(window: &Window) -> Self {
+    pub fn new(window: &Window, settings: &RenderSettings) -> Self {
         // Initialize wgpu
         let surface = wgpu::Surface::create(window);
-        let (device, queue) = futures::executor::block_on(async {
+        let (adapter_info, device, queue) = futures::executor::block_on(async {
             let adapter = wgpu::Adapter::request(
                 &wgpu::RequestAdapterOptions {
-                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    power_preference: settings.power_preference,
                     compatible_surface: Some(&surface),
                 },
                 wgpu::BackendBit::PRIMARY,
@@ -28,14 +47,7 @@ impl RenderingState {
             .await
             .expect("Request adapter");
 
-            adapter
-                .request_device(&wgpu::DeviceDescriptor {
-                    extensions: wgpu::Extensions {
-                        anisotropic_filtering: false,
-                    },
-                    limits: wgpu::Limits::default(),
-                })
-                .await
+            request_device(adapter).await
         });
 
         let (swap_chain, swap_chain_descriptor) = {
@@ -45,7 +57,7 @@ impl RenderingState {
                 format: wgpu::TextureFormat::Bgra8UnormSrgb,
                 width: size.width,
                 height: size.height,
-                present_mode: wgpu::PresentMode::Fifo,
+                present_mode: settings.present_mode,
             };
 
             (device.create_swap_chain(&surface, &sc_desc), sc_desc)
@@ -58,12 +70,84 @@ impl RenderingState {
         );
 
         Self {
-            surface,
+            adapter_info,
+            surface: Some(surface),
+            device,
+            queue,
+            swap_chain: Some(swap_chain),
+            swap_chain_descriptor,
+            viewport,
+        }
+    }
+
+    /// Sets up a device without a window or surface, for drawing into an offscreen texture
+    /// of the given size. If no hardware adapter is around, any adapter wgpu can find is used,
+    /// which on a build box is usually a software Vulkan implementation like lavapipe or SwiftShader.
+    pub fn headless(
+        width: u32,
+        height: u32,
+        settings: &RenderSettings,
+    ) -> Result<Self, failure::Error> {
+        let (adapter_info, device, queue) = futures::executor::block_on(async {
+            let adapter = match wgpu::Adapter::request(
+                &wgpu::RequestAdapterOptions {
+                    power_preference: settings.power_preference,
+                    compatible_surface: None,
+                },
+                wgpu::BackendBit::PRIMARY,
+            )
+            .await
+            {
+                Some(adapter) => Some(adapter),
+                None => {
+                    log::warn!("no primary adapter found, falling back to any available adapter");
+                    wgpu::Adapter::request(
+                        &wgpu::RequestAdapterOptions {
+                            power_preference: wgpu::PowerPreference::LowPower,
+                            compatible_surface: None,
+                        },
+                        wgpu::BackendBit::all(),
+                    )
+                    .await
+                }
+            };
+
+            match adapter {
+                Some(adapter) => Ok(request_device(adapter).await),
+                None => Err(failure::err_msg(
+                    "no adapter available for headless rendering",
+                )),
+            }
+        })?;
+
+        let swap_chain_descriptor = wgpu::SwapChainDescriptor {
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: settings.present_mode,
+        };
+
+        let viewport = Viewport::with_physical_size(iced_winit::Size::new(width, height), 1.0);
+
+        Ok(Self {
+            adapter_info,
+            surface: None,
             device,
             queue,
-            swap_chain,
+            swap_chain: None,
             swap_chain_descriptor,
             viewport,
+        })
+    }
+
+    /// Rebuilds the swap chain, if there is one, to pick up changes to its descriptor.
+    pub fn rebuild_swap_chain(&mut self) {
+        if let Some(surface) = &self.surface {
+            self.swap_chain = Some(
+                self.device
+                    .create_swap_chain(surface, &self.swap_chain_descriptor),
+            );
         }
     }
 
@@ -74,8 +158,6 @@ impl RenderingState {
         );
         self.swap_chain_descriptor.width = screen.width;
         self.swap_chain_descriptor.height = screen.height;
-        self.swap_chain = self
-            .device
-            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+        self.rebuild_swap_chain();
     }
 }
@@ -0,0 +1,68 @@
+use hexa::iced_wgpu::wgpu;
+
+/// How the renderer should go about drawing, passed to `Renderer::new` and
+/// changeable later with `Renderer::set_settings`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    /// How many samples to take per pixel, for antialiasing.
+    /// Defaults to 4, the most every adapter supports; see `supported_sample_count`.
+    pub msaa: u32,
+    /// Fifo waits for vertical blank, which is vsync; Immediate doesn't, and may tear.
+    pub present_mode: wgpu::PresentMode,
+    /// Only read when the renderer is created, since it decides which adapter is used.
+    pub power_preference: wgpu::PowerPreference,
+    /// What shows through wherever there are no tiles or sprites.
+    pub background: wgpu::Color,
+}
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            msaa: GUARANTEED_SAMPLE_COUNT,
+            present_mode: wgpu::PresentMode::Fifo,
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            background: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+        }
+    }
+}
+
+/// wgpu can't tell us which sample counts an adapter supports, but Vulkan requires every
+/// device to support 1 and 4 for color and depth attachments, so that much is always safe.
+const GUARANTEED_SAMPLE_COUNT: u32 = 4;
+/// The most any adapter we've come across supports.
+const MAX_SAMPLE_COUNT: u32 = 16;
+
+/// The closest sample count to the one requested that the adapter might handle:
+/// a power of two no larger than 16, and no larger than 4 on software adapters.
+/// Anything over 4 has to be asked for, and since it can't be checked, it's on whoever asks
+/// to know their hardware supports it; if it doesn't, creating the pipelines fails.
+pub fn supported_sample_count(requested: u32, adapter: &wgpu::AdapterInfo) -> u32 {
+    let max = match adapter.device_type {
+        wgpu::DeviceType::Cpu => GUARANTEED_SAMPLE_COUNT,
+        _ => MAX_SAMPLE_COUNT,
+    };
+    let clamped = requested.max(1).min(max);
+    // round down to a power of two
+    let supported = 1 << (31 - clamped.leading_zeros());
+
+    if supported != requested {
+        log::warn!(
+            "{}x MSAA isn't supported on {}, using {}x instead",
+            requested,
+            adapter.name,
+            supported
+        );
+    } else if supported > GUARANTEED_SAMPLE_COUNT {
+        log::warn!(
+            "{}x MSAA might not be supported on {}, only {}x is guaranteed",
+            supported,
+            adapter.name,
+            GUARANTEED_SAMPLE_COUNT
+        );
+    }
+    supported
+}
@@ -0,0 +1,175 @@
+//! Turning shaders into shader modules, and explaining what went wrong when that isn't possible.
+//! The build script compiles every shader into SPIR-V ahead of time; GLSL is only compiled at
+//! runtime when hot reloading.
+use crate::RenderingState;
+use hexa::iced_wgpu::wgpu;
+use std::fmt;
+
+/// Draws everything magenta, so that whatever's using it is obviously broken.
+const ERROR_FRAGMENT_SHADER: (&[u8], &str) = (
+    include_bytes!(concat!(env!("OUT_DIR"), "/error/shader.frag.spv")),
+    "error/shader.frag",
+);
+
+/// The fragment shaders the build script built as the error shader because they didn't
+/// compile, which it only does when asked to with `HEXA_SHADER_FALLBACK`.
+/// Each is a label, a newline, and shaderc's errors, and they're separated by nulls.
+const BUILD_FALLBACKS: &str = include_str!(concat!(env!("OUT_DIR"), "/fallbacks.txt"));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Vertex,
+    Fragment,
+}
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Stage::Vertex => "vertex",
+            Stage::Fragment => "fragment",
+        })
+    }
+}
+
+/// Why a shader couldn't be turned into a module.
+#[derive(Debug, Clone)]
+pub struct ShaderError {
+    pub stage: Stage,
+    /// The label the shader was compiled with, e.g. "hex/shader.vert".
+    pub label: String,
+    /// The line of the first error shaderc reported, if it reported one.
+    pub line: Option<u32>,
+    pub message: String,
+}
+impl ShaderError {
+    fn new(stage: Stage, label: &str, message: String) -> Self {
+        // shaderc reports errors as "label:line: error: ..."
+        let line = message.lines().find_map(|l| {
+            l.strip_prefix(label)?
+                .strip_prefix(':')?
+                .split(':')
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+        });
+
+        Self {
+            stage,
+            label: label.to_string(),
+            line,
+            message: message.trim().to_string(),
+        }
+    }
+}
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "couldn't load {} shader {}", self.stage, self.label)?;
+        if let Some(line) = self.line {
+            write!(f, " (line {})", line)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+impl std::error::Error for ShaderError {}
+
+/// Logs why each fragment shader the build script swapped for the error shader didn't compile,
+/// so that a build made with `HEXA_SHADER_FALLBACK` can't quietly ship one.
+pub fn report_build_fallbacks() {
+    for fallback in BUILD_FALLBACKS.split('\0').filter(|f| !f.is_empty()) {
+        let mut parts = fallback.splitn(2, '\n');
+        let label = parts.next().unwrap_or_default();
+        let message = parts.next().unwrap_or_default().to_string();
+        log::error!(
+            "{}; drawing with the error shader instead",
+            ShaderError::new(Stage::Fragment, label, message)
+        );
+    }
+}
+
+/// Loads a shader the build script compiled. The first element of each pair is the SPIR-V,
+/// the second the label the shader was compiled with.
+fn load(
+    (spirv, label): (&[u8], &str),
+    stage: Stage,
+    rs: &RenderingState,
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    let data = wgpu::read_spirv(std::io::Cursor::new(spirv))
+        .map_err(|e| ShaderError::new(stage, label, format!("invalid SPIR-V: {}", e)))?;
+    Ok(rs.device.create_shader_module(&data))
+}
+
+/// Loads a pair of shaders the build script compiled. If the fragment shader can't be loaded,
+/// this logs why and uses one that draws everything magenta instead. The vertex shader can't be
+/// swapped out like this, since the pipeline's buffers are laid out for it, so its errors are
+/// still returned.
+pub fn load_shaders(
+    vs: (&[u8], &str),
+    fs: (&[u8], &str),
+    rs: &RenderingState,
+) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule), ShaderError> {
+    let vs_module = load(vs, Stage::Vertex, rs)?;
+    let fs_module = load(fs, Stage::Fragment, rs).or_else(|e| {
+        log::error!("{}; drawing with the error shader instead", e);
+        load(ERROR_FRAGMENT_SHADER, Stage::Fragment, rs)
+    })?;
+
+    Ok((vs_module, fs_module))
+}
+
+/// Resolves `#include "name.glsl"` to `shader/include/name.glsl`, like the build script does.
+#[cfg(feature = "hot_reload")]
+fn compile_options<'a>() -> Option<shaderc::CompileOptions<'a>> {
+    let include_dir = crate::hot_reload::shader_dir().join("include");
+    let mut options = shaderc::CompileOptions::new()?;
+    options.set_include_callback(move |name, _, _, _| {
+        let path = include_dir.join(name);
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+        Ok(shaderc::ResolvedInclude {
+            resolved_name: format!("include/{}", name),
+            content,
+        })
+    });
+    Some(options)
+}
+
+#[cfg(feature = "hot_reload")]
+fn compile(
+    compiler: &mut shaderc::Compiler,
+    options: &shaderc::CompileOptions,
+    (src, label): (&str, &str),
+    stage: Stage,
+    rs: &RenderingState,
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    let kind = match stage {
+        Stage::Vertex => shaderc::ShaderKind::Vertex,
+        Stage::Fragment => shaderc::ShaderKind::Fragment,
+    };
+    let spirv = compiler
+        .compile_into_spirv(src, kind, label, "main", Some(options))
+        .map_err(|e| {
+            let message = match e {
+                shaderc::Error::CompilationError(_, message) => message,
+                other => other.to_string(),
+            };
+            ShaderError::new(stage, label, message)
+        })?;
+
+    load((spirv.as_binary_u8(), label), stage, rs)
+}
+
+/// Compiles GLSL, for when the SPIR-V the build script made is out of date.
+#[cfg(feature = "hot_reload")]
+pub fn compile_shaders(
+    vs: (&str, &str),
+    fs: (&str, &str),
+    rs: &RenderingState,
+) -> Result<(wgpu::ShaderModule, wgpu::ShaderModule), ShaderError> {
+    let (mut compiler, options) = shaderc::Compiler::new()
+        .zip(compile_options())
+        .ok_or_else(|| ShaderError::new(Stage::Vertex, vs.1, "couldn't start shaderc".into()))?;
+    Ok((
+        compile(&mut compiler, &options, vs, Stage::Vertex, rs)?,
+        compile(&mut compiler, &options, fs, Stage::Fragment, rs)?,
+    ))
+}
@@ -0,0 +1,305 @@
+//! Shadows cast by the sun. Tiles and sprites are drawn into a depth texture from the sun's
+//! point of view before anything else, then the hex and quad shaders compare against it to find
+//! out whether anything stands between them and the sun. The rest of the lighting lives here too,
+//! since everything that samples the shadows needs to know what color the sun is.
+use crate::{texture, RenderingState};
+use hexa::{iced_wgpu::wgpu, iced_winit::Color, Camera, Lighting};
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+/// How many texels wide and tall the shadow map is.
+const SIZE: u32 = 2048;
+/// How far from what the camera is looking at shadows are drawn,
+/// relative to how far the camera is from it.
+const REACH: f32 = 1.5;
+/// How far beyond that something can be and still cast a shadow onto it.
+const CASTER_MARGIN: f32 = 32.0;
+
+/// nalgebra's projections put depth between -1 and 1, like OpenGL, but wgpu wants 0 to 1.
+#[rustfmt::skip]
+fn opengl_to_wgpu() -> Matrix4<f32> {
+    Matrix4::new(
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 0.5, 0.5,
+        0.0, 0.0, 0.0, 1.0,
+    )
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Uniforms {
+    light_view_proj: Matrix4<f32>,
+    /// xyz is the normalized direction sunlight travels in, w is 1.0 for flat shading.
+    sun_direction: Vector4<f32>,
+    sun_color: Vector4<f32>,
+    ambient: Vector4<f32>,
+}
+unsafe impl bytemuck::Pod for Uniforms {}
+unsafe impl bytemuck::Zeroable for Uniforms {}
+impl Uniforms {
+    fn new(camera: &Camera) -> Self {
+        let mut uniforms = Self {
+            light_view_proj: Matrix4::identity(),
+            sun_direction: Vector4::zeros(),
+            sun_color: Vector4::zeros(),
+            ambient: Vector4::zeros(),
+        };
+        uniforms.update_lighting(&Lighting::default());
+        uniforms.update_light_view_proj(camera);
+        uniforms
+    }
+
+    fn update_lighting(&mut self, lighting: &Lighting) {
+        let color = |c: Color| Vector4::new(c.r, c.g, c.b, c.a);
+        let flat = if lighting.flat_shading { 1.0 } else { 0.0 };
+
+        self.sun_direction = lighting
+            .sun_direction
+            .try_normalize(std::f32::EPSILON)
+            .unwrap_or_else(|| -Vector3::z())
+            .push(flat);
+        self.sun_color = color(lighting.sun_color);
+        self.ambient = color(lighting.ambient);
+    }
+
+    /// Fits the sun's orthographic projection around what the camera is looking at.
+    fn update_light_view_proj(&mut self, camera: &Camera) {
+        let direction = self.sun_direction.xyz();
+        // any up will do, so long as it isn't the way the sun is shining
+        let up = if direction.cross(&Vector3::z()).norm() < 1e-4 {
+            Vector3::y()
+        } else {
+            Vector3::z()
+        };
+        let view = Matrix4::look_at_rh(&Point3::origin(), &Point3::from(direction), &up);
+
+        let radius = (camera.eye.coords.norm() * REACH).max(1.0);
+        let center = view.transform_point(&camera.target);
+        // snapping to whole texels keeps the edges of shadows from crawling as the camera pans
+        let texel = 2.0 * radius / SIZE as f32;
+        let snap = |v: f32| (v / texel).round() * texel;
+        let (x, y) = (snap(center.x), snap(center.y));
+
+        // the view looks down -z, so depths are negated
+        let proj = Matrix4::new_orthographic(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            -center.z - radius - CASTER_MARGIN,
+            -center.z + radius,
+        );
+        self.light_view_proj = opengl_to_wgpu() * proj * view;
+    }
+}
+
+pub struct ShadowMap {
+    uniforms: Uniforms,
+    uniform_buffer: wgpu::Buffer,
+    /// Kept so the sun's projection can be fit around it again when the lighting changes.
+    camera: Camera,
+    pub texture: texture::Texture,
+    /// Just the uniforms, for drawing into the shadow map.
+    pub light_bind_group_layout: wgpu::BindGroupLayout,
+    pub light_bind_group: wgpu::BindGroup,
+    /// The uniforms, the shadow map and its comparison sampler, for drawing with shadows.
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+impl ShadowMap {
+    pub fn new(rs: &RenderingState, camera: &Camera) -> Self {
+        let uniforms = Uniforms::new(camera);
+        let uniform_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[uniforms]),
+            wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        );
+        let uniform_binding = || wgpu::Binding {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer {
+                buffer: &uniform_buffer,
+                range: 0..std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+            },
+        };
+
+        let texture =
+            texture::Texture::create_depth_texture(&rs.device, (SIZE, SIZE), 1, "shadow_map");
+
+        let light_bind_group_layout =
+            rs.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    bindings: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                    }],
+                    label: Some("light_bind_group_layout"),
+                });
+        let light_bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            bindings: &[uniform_binding()],
+            label: Some("light_bind_group"),
+        });
+
+        let bind_group_layout =
+            rs.device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    bindings: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                            ty: wgpu::BindingType::UniformBuffer { dynamic: false },
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStage::FRAGMENT,
+                            ty: wgpu::BindingType::SampledTexture {
+                                multisampled: false,
+                                dimension: wgpu::TextureViewDimension::D2,
+                                component_type: wgpu::TextureComponentType::Float,
+                            },
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStage::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler { comparison: true },
+                        },
+                    ],
+                    label: Some("shadow_bind_group_layout"),
+                });
+        let bind_group = rs.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            bindings: &[
+                uniform_binding(),
+                wgpu::Binding {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::Binding {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        });
+
+        Self {
+            uniforms,
+            uniform_buffer,
+            camera: camera.clone(),
+            texture,
+            light_bind_group_layout,
+            light_bind_group,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    /// Everything the sun can see, for culling what's drawn into the shadow map.
+    pub fn light_view_proj(&self) -> Matrix4<f32> {
+        self.uniforms.light_view_proj
+    }
+
+    pub fn set_camera(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        camera: &Camera,
+    ) {
+        self.camera = camera.clone();
+        self.uniforms.update_light_view_proj(camera);
+        self.upload_uniforms(encoder, rs);
+    }
+
+    pub fn set_lighting(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        rs: &RenderingState,
+        lighting: &Lighting,
+    ) {
+        self.uniforms.update_lighting(lighting);
+        self.uniforms.update_light_view_proj(&self.camera);
+        self.upload_uniforms(encoder, rs);
+    }
+
+    fn upload_uniforms(&self, encoder: &mut wgpu::CommandEncoder, rs: &RenderingState) {
+        let staging_buffer = rs.device.create_buffer_with_data(
+            bytemuck::cast_slice(&[self.uniforms]),
+            wgpu::BufferUsage::COPY_SRC,
+        );
+
+        encoder.copy_buffer_to_buffer(
+            &staging_buffer,
+            0,
+            &self.uniform_buffer,
+            0,
+            std::mem::size_of::<Uniforms>() as wgpu::BufferAddress,
+        );
+    }
+
+    /// A pass that only writes to the shadow map, clearing it first.
+    pub fn begin_render_pass<'a>(
+        &'a self,
+        encoder: &'a mut wgpu::CommandEncoder,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachmentDescriptor {
+                attachment: &self.texture.view,
+                depth_load_op: wgpu::LoadOp::Clear,
+                depth_store_op: wgpu::StoreOp::Store,
+                clear_depth: 1.0,
+                stencil_load_op: wgpu::LoadOp::Clear,
+                stencil_store_op: wgpu::StoreOp::Store,
+                clear_stencil: 0,
+            }),
+        })
+    }
+}
+
+/// The depth-only pipeline tiles and sprites are drawn into the shadow map with.
+/// Nothing is culled, since sprites can be seen from behind by the sun,
+/// and slope scaled depth bias keeps surfaces from shadowing themselves.
+pub fn pipeline(
+    rs: &RenderingState,
+    layout: &wgpu::PipelineLayout,
+    (vs_module, fs_module): &(wgpu::ShaderModule, wgpu::ShaderModule),
+    vertex_buffers: &[wgpu::VertexBufferDescriptor],
+) -> wgpu::RenderPipeline {
+    rs.device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            layout,
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: wgpu::CullMode::None,
+                depth_bias: 2,
+                depth_bias_slope_scale: 2.0,
+                depth_bias_clamp: 0.0,
+            }),
+            color_states: &[],
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_stencil_state: Some(wgpu::DepthStencilStateDescriptor {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil_front: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_back: wgpu::StencilStateFaceDescriptor::IGNORE,
+                stencil_read_mask: 0,
+                stencil_write_mask: 0,
+            }),
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers,
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        })
+}
@@ -1,5 +1,5 @@
 use hexa::iced_wgpu::wgpu;
-use image::{DynamicImage, GenericImageView, ImageError};
+use image::RgbaImage;
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -10,36 +10,28 @@ pub struct Texture {
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float; // 1.
 
-    pub fn from_bytes(
+    /// Uploads the images as the layers of one texture array, in order.
+    pub fn from_images(
         device: &wgpu::Device,
-        image_bytes: Vec<(&[u8], &str)>,
+        imgs: &[(String, RgbaImage)],
         main_label: &str,
     ) -> Result<(Self, wgpu::CommandBuffer), failure::Error> {
-        Self::from_image(
-            device,
-            image_bytes
-                .into_iter()
-                .map(|(b, l)| Ok((image::load_from_memory(b)?, l)))
-                .collect::<Result<Vec<(DynamicImage, &str)>, ImageError>>()?,
-            main_label,
-        )
-    }
-
-    pub fn from_image(
-        device: &wgpu::Device,
-        imgs: Vec<(DynamicImage, &str)>,
-        main_label: &str,
-    ) -> Result<(Self, wgpu::CommandBuffer), failure::Error> {
-        let dimensions = imgs.first().expect("no images").0.dimensions();
+        let dimensions = imgs
+            .first()
+            .ok_or_else(|| failure::format_err!("{} has no images", main_label))?
+            .1
+            .dimensions();
         let img_count = imgs.len() as u32;
 
-        for (img, label) in &imgs {
-            assert_eq!(
+        for (label, img) in imgs {
+            failure::ensure!(
+                img.dimensions() == dimensions,
+                "image labeled {} in {} is {:?}, but the first is {:?}",
+                label,
+                main_label,
                 img.dimensions(),
-                dimensions,
-                "image labeled {} has dimensions that don't match!",
-                label
-            )
+                dimensions
+            );
         }
 
         let size = wgpu::Extent3d {
@@ -62,11 +54,8 @@ impl Texture {
             label: Some("texture_buffer_copy_encoder"),
         });
 
-        for (i, (img, label)) in imgs.into_iter().enumerate() {
-            let rgba = img
-                .as_rgba8()
-                .unwrap_or_else(|| panic!("image with {} label invalid", label));
-            let buffer = device.create_buffer_with_data(&rgba, wgpu::BufferUsage::COPY_SRC);
+        for (i, (_, img)) in imgs.iter().enumerate() {
+            let buffer = device.create_buffer_with_data(img, wgpu::BufferUsage::COPY_SRC);
             encoder.copy_buffer_to_texture(
                 wgpu::BufferCopyView {
                     buffer: &buffer,
@@ -118,16 +107,18 @@ impl Texture {
         ))
     }
 
+    /// A depth texture that can be rendered into and then sampled with its comparison sampler,
+    /// usually the size of the swap chain, but shadow maps are sized however they like.
     pub fn create_depth_texture(
         device: &wgpu::Device,
-        sc_desc: &wgpu::SwapChainDescriptor,
+        (width, height): (u32, u32),
         sample_count: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
             // 2.
-            width: sc_desc.width,
-            height: sc_desc.height,
+            width,
+            height,
             depth: 1,
         };
         let desc = wgpu::TextureDescriptor {
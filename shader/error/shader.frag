@@ -0,0 +1,11 @@
+// shader.frag
+#version 450
+
+// Draws everything magenta, so whatever's using it is obviously broken.
+// The build script swaps this in for any fragment shader that doesn't compile.
+
+layout(location=0) out vec4 f_color;
+
+void main() {
+    f_color = vec4(1.0, 0.0, 1.0, 1.0);
+}
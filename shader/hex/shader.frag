@@ -3,11 +3,68 @@
 
 layout(location=0) in vec2 v_tex_coords;
 layout(location=1) flat in uint v_which_tex;
+layout(location=2) flat in uint v_face;
+layout(location=3) in vec2 v_local;
+layout(location=4) flat in vec4 v_tint;
+layout(location=5) flat in vec4 v_outline;
+layout(location=6) in vec3 v_normal;
+layout(location=7) in vec3 v_shadow;
 layout(location=0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform texture2DArray t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
+layout(set=2, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+layout(set=2, binding=1) uniform texture2D t_shadow;
+layout(set=2, binding=2) uniform samplerShadow s_shadow;
+
+#include "sunlight.glsl"
+
+const float HAT_OUTLINE_WIDTH = 0.08;
+const float BUTT_OUTLINE_WIDTH = 0.04;
+
+// how far this fragment is from the nearest edge of the face it's on
+float edge_distance() {
+    if (v_face == 0) {
+        // the hat is a hexagon; its sides face every sixty degrees
+        float d = 0.0;
+        for (int i = 0; i < 6; i++) {
+            float a = radians(60.0 * i);
+            d = max(d, dot(v_local, vec2(cos(a), sin(a))));
+        }
+        return (0.866025 - d) / HAT_OUTLINE_WIDTH;
+    }
+
+    vec2 uv = min(v_tex_coords, 1.0 - v_tex_coords);
+    return min(uv.x, uv.y) / BUTT_OUTLINE_WIDTH;
+}
+
+// which way this fragment faces; with flat shading, every fragment on a face agrees
+vec3 normal() {
+    if (u_sun_direction.w == 0.0)
+        return normalize(v_normal);
+    if (v_face == 0)
+        return vec3(0.0, 0.0, 1.0);
+
+    // the sides of the butt face every sixty degrees, so snap to the nearest of those
+    float side = radians(60.0);
+    float a = round(atan(v_local.y, v_local.x) / side) * side;
+    return vec3(cos(a), sin(a), 0.0);
+}
+
 void main() {
-    f_color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_coords, v_which_tex));
+    vec4 color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_coords, v_which_tex));
+    float sun = max(dot(normal(), -u_sun_direction.xyz), 0.0);
+    color.rgb *= u_ambient.rgb + u_sun_color.rgb * sun * sunlight(v_shadow);
+    color.rgb = mix(color.rgb, v_tint.rgb, v_tint.a);
+    if (v_outline.a > 0.0 && edge_distance() < 1.0)
+        color.rgb = mix(color.rgb, v_outline.rgb, v_outline.a);
+
+    f_color = color;
 }
@@ -4,8 +4,15 @@
 layout(location=0) in vec3 a_position;
 layout(location=1) in vec2 a_tex_coords;
 layout(location=2) in uint a_which_tex;
+layout(location=3) in vec3 a_normal;
 layout(location=0) out vec2 v_tex_coords;
 layout(location=1) flat out uint v_which_tex;
+layout(location=2) flat out uint v_face;
+layout(location=3) out vec2 v_local;
+layout(location=4) flat out vec4 v_tint;
+layout(location=5) flat out vec4 v_outline;
+layout(location=6) out vec3 v_normal;
+layout(location=7) out vec3 v_shadow;
 
 layout(set=1, binding=0)
 uniform Uniforms {
@@ -15,6 +22,8 @@ uniform Uniforms {
 struct Instance {
     mat4 s_model;
     uvec4 tex_index;
+    vec4 tint;
+    vec4 outline;
 };
 
 layout(set=1, binding=1)
@@ -22,12 +31,37 @@ buffer Instances {
     Instance instances[];
 };
 
+// the indexes of the instances that survived culling
+layout(set=1, binding=2)
+buffer Visible {
+    uint visible[];
+};
+
+layout(set=2, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+
+#include "shadow_coords.glsl"
+
 void main() {
-    Instance i = instances[gl_InstanceIndex];
+    Instance i = instances[visible[gl_InstanceIndex]];
 
-    gl_Position = u_view_proj * i.s_model * vec4(a_position, 1.0);
+    vec4 world = i.s_model * vec4(a_position, 1.0);
+    gl_Position = u_view_proj * world;
+    v_shadow = shadow_coords(u_light_view_proj, world);
 
     v_tex_coords = a_tex_coords;
+    v_face = a_which_tex;
+    v_local = a_position.xy;
+    v_tint = i.tint;
+    v_outline = i.outline;
+    // tiles are only ever scaled along z, and normals either point along z or lie flat,
+    // so the model matrix keeps them pointing the right way without an inverse transpose
+    v_normal = normalize(mat3(i.s_model) * a_normal);
     if (a_which_tex == 0)
         v_which_tex = i.tex_index.x;
     else
@@ -0,0 +1,5 @@
+// shader.frag
+#version 450
+
+// tiles are solid, so there's nothing to do but let their depth be written
+void main() {}
@@ -0,0 +1,33 @@
+// shader.vert
+#version 450
+
+layout(location=0) in vec3 a_position;
+
+struct Instance {
+    mat4 s_model;
+    uvec4 tex_index;
+    vec4 tint;
+    vec4 outline;
+};
+
+layout(set=1, binding=1)
+buffer Instances {
+    Instance instances[];
+};
+
+// the indexes of the instances the sun can see
+layout(set=1, binding=2)
+buffer Visible {
+    uint visible[];
+};
+
+layout(set=2, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+};
+
+void main() {
+    Instance i = instances[visible[gl_InstanceIndex]];
+
+    gl_Position = u_light_view_proj * i.s_model * vec4(a_position, 1.0);
+}
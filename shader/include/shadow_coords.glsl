@@ -0,0 +1,8 @@
+// shadow_coords.glsl
+
+// where a point in world space is on the shadow map, and how far it is from the sun,
+// given the sun's view projection matrix
+vec3 shadow_coords(mat4 light_view_proj, vec4 world) {
+    vec4 light = light_view_proj * world;
+    return vec3(light.xy * vec2(0.5, -0.5) + 0.5, light.z);
+}
@@ -0,0 +1,11 @@
+// sunlight.glsl
+// t_shadow and s_shadow, the shadow map and how it's sampled, must be declared before this.
+
+// how much sunlight reaches a fragment, from none at all in shadow to all of it,
+// given where it is on the shadow map
+float sunlight(vec3 shadow) {
+    // nothing is known to be in the way of anything outside the shadow map
+    if (any(lessThan(shadow, vec3(0.0))) || any(greaterThan(shadow, vec3(1.0))))
+        return 1.0;
+    return texture(sampler2DShadow(t_shadow, s_shadow), shadow);
+}
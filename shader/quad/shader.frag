@@ -3,11 +3,28 @@
 
 layout(location=0) in vec2 v_tex_coords;
 layout(location=1) flat in uint v_which_tex;
+layout(location=2) in vec3 v_shadow;
 layout(location=0) out vec4 f_color;
 
 layout(set = 0, binding = 0) uniform texture2DArray t_diffuse;
 layout(set = 0, binding = 1) uniform sampler s_diffuse;
 
+layout(set=2, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+layout(set=2, binding=1) uniform texture2D t_shadow;
+layout(set=2, binding=2) uniform samplerShadow s_shadow;
+
+#include "sunlight.glsl"
+
 void main() {
-    f_color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_coords, v_which_tex));
+    vec4 color = texture(sampler2DArray(t_diffuse, s_diffuse), vec3(v_tex_coords, v_which_tex));
+    // sprites are flat cutouts, so they're lit as though they always face the sun
+    color.rgb *= u_ambient.rgb + u_sun_color.rgb * sunlight(v_shadow);
+
+    f_color = color;
 }
@@ -4,6 +4,7 @@
 layout(location=0) in vec3 a_position;
 layout(location=0) out vec2 v_tex_coords;
 layout(location=1) flat out uint v_which_tex;
+layout(location=2) out vec3 v_shadow;
 
 layout(set=1, binding=0)
 uniform Uniforms {
@@ -23,15 +24,33 @@ buffer Instances {
     Instance instances[];
 };
 
+// the indexes of the instances that survived culling
+layout(set=1, binding=2)
+buffer Visible {
+    uint visible[];
+};
+
+layout(set=2, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+
+#include "shadow_coords.glsl"
+
 void main() {
-    Instance i = instances[gl_InstanceIndex];
+    Instance i = instances[visible[gl_InstanceIndex]];
 
-    gl_Position = u_view_proj * vec4(
+    vec4 world = vec4(
         i.s_position
             + u_camera_right.xyz * a_position.x * i.s_scale.x
             + u_camera_up.xyz * a_position.y * i.s_scale.y,
 	1.0
     );
+    gl_Position = u_view_proj * world;
+    v_shadow = shadow_coords(u_light_view_proj, world);
 
     v_tex_coords = vec2(a_position.x + 0.5, 0.5 - a_position.y);
     v_which_tex = i.tex_index.x;
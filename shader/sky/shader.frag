@@ -0,0 +1,22 @@
+// shader.frag
+#version 450
+
+layout(location=0) in vec2 v_ndc;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0)
+uniform Uniforms {
+    mat4 u_inverse_view_proj;
+    vec4 u_zenith;
+    vec4 u_horizon;
+};
+
+void main() {
+    // which way the camera is looking through this fragment
+    vec4 near = u_inverse_view_proj * vec4(v_ndc, -1.0, 1.0);
+    vec4 far = u_inverse_view_proj * vec4(v_ndc, 1.0, 1.0);
+    vec3 direction = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    // the horizon's color fades quickly, leaving a thin band of it just above the horizon
+    f_color = mix(u_horizon, u_zenith, sqrt(max(direction.z, 0.0)));
+}
@@ -0,0 +1,15 @@
+// shader.vert
+#version 450
+
+const vec2 positions[3] = vec2[3](
+    vec2(-1.0, -1.0),
+    vec2( 3.0, -1.0),
+    vec2(-1.0,  3.0)
+);
+
+layout(location=0) out vec2 v_ndc;
+
+void main() {
+    v_ndc = positions[gl_VertexIndex];
+    gl_Position = vec4(v_ndc, 0.0, 1.0);
+}
@@ -0,0 +1,53 @@
+// shader.frag
+#version 450
+
+layout(location=0) in vec3 v_world;
+layout(location=1) in vec3 v_shadow;
+layout(location=0) out vec4 f_color;
+
+layout(set=0, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+    vec4 u_plane;
+    vec4 u_eye;
+    vec4 u_time;
+};
+
+layout(set=1, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+layout(set=1, binding=1) uniform texture2D t_shadow;
+layout(set=1, binding=2) uniform samplerShadow s_shadow;
+
+#include "sunlight.glsl"
+
+const vec3 WATER_COLOR = vec3(0.1, 0.35, 0.5);
+
+// the slope of one wave rolling along `direction`
+vec2 wave(vec2 direction, float frequency, float speed, float height) {
+    float phase = dot(v_world.xy, direction) * frequency + u_time.x * speed;
+    return direction * height * frequency * cos(phase);
+}
+
+void main() {
+    // a few waves rolling different ways, so the pattern doesn't obviously repeat
+    vec2 slope = wave(normalize(vec2(1.0, 0.3)), 2.1, 1.3, 0.02)
+        + wave(normalize(vec2(-0.4, 1.0)), 3.3, 1.7, 0.012)
+        + wave(normalize(vec2(-1.0, -0.7)), 5.7, 2.3, 0.006);
+    vec3 normal = normalize(vec3(-slope, 1.0));
+    vec3 to_eye = normalize(u_eye.xyz - v_world);
+    vec3 to_sun = -u_sun_direction.xyz;
+    float sun = sunlight(v_shadow);
+
+    vec3 color = WATER_COLOR * (u_ambient.rgb + u_sun_color.rgb * max(dot(normal, to_sun), 0.0) * sun);
+    float glint = pow(max(dot(reflect(-to_sun, normal), to_eye), 0.0), 64.0);
+    color += u_sun_color.rgb * glint * sun;
+
+    // water looked at from the side reflects more, and lets less of what's beneath show through
+    float fresnel = pow(1.0 - max(dot(normal, to_eye), 0.0), 3.0);
+    f_color = vec4(color, mix(0.6, 0.95, fresnel));
+}
@@ -0,0 +1,43 @@
+// shader.vert
+#version 450
+
+// two triangles making up a square, scaled and moved into place by u_plane
+const vec2 corners[6] = vec2[6](
+    vec2(-1.0, -1.0),
+    vec2( 1.0,  1.0),
+    vec2( 1.0, -1.0),
+    vec2(-1.0, -1.0),
+    vec2(-1.0,  1.0),
+    vec2( 1.0,  1.0)
+);
+
+layout(location=0) out vec3 v_world;
+layout(location=1) out vec3 v_shadow;
+
+layout(set=0, binding=0)
+uniform Uniforms {
+    mat4 u_view_proj;
+    // xy is the middle of the water, z is sea level, w is how far it reaches from the middle
+    vec4 u_plane;
+    vec4 u_eye;
+    // x is how many seconds the waves have been moving for
+    vec4 u_time;
+};
+
+layout(set=1, binding=0)
+uniform Light {
+    mat4 u_light_view_proj;
+    vec4 u_sun_direction;
+    vec4 u_sun_color;
+    vec4 u_ambient;
+};
+
+#include "shadow_coords.glsl"
+
+void main() {
+    vec4 world = vec4(u_plane.xy + corners[gl_VertexIndex] * u_plane.w, u_plane.z, 1.0);
+    gl_Position = u_view_proj * world;
+    v_world = world.xyz;
+
+    v_shadow = shadow_coords(u_light_view_proj, world);
+}
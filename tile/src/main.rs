@@ -20,7 +20,10 @@ fn main() {
 
     let mut modifiers = ModifiersState::default();
 
-    let mut renderer = render::Renderer::new(&window);
+    let mut renderer = render::Renderer::new(&window, Default::default()).unwrap_or_else(|e| {
+        log::error!("couldn't create renderer: {}", e);
+        std::process::exit(1)
+    });
 
     #[cfg(not(feature = "dyn"))]
     let mut scene = hackstead_scene::HacksteadScene::new(&mut renderer);